@@ -0,0 +1,223 @@
+#![allow(clippy::needless_return)]
+
+use std::cmp::Ordering;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::pow::Pow;
+use num_traits::{FromPrimitive, Signed, ToPrimitive, Zero};
+
+use crate::err::Err;
+use crate::stackitem::Value;
+
+// Numbers are promoted from integers to rationals to floats as needed
+#[derive(Clone, Debug)]
+pub enum Number {
+    Int(BigInt),
+    Rational(BigRational),
+    Float(f64)
+}
+
+impl Number {
+    pub fn from_value(c: &Value) -> Option<Number> {
+        match c {
+            Value::IntValue(i) => return Some(Number::Int(i.clone())),
+            Value::RationalValue(r) => return Some(Number::Rational(r.clone())),
+            Value::FloatValue(f) => return Some(Number::Float(*f)),
+            _ => return None
+        }
+    }
+
+    // Rationals with a denominator of 1 become integers
+    pub fn into_value(self) -> Value {
+        match self {
+            Number::Int(i) => return Value::IntValue(i),
+            Number::Rational(r) => {
+                if r.is_integer() {
+                    return Value::IntValue(r.to_integer());
+                } else {
+                    return Value::RationalValue(r);
+                }
+            }
+            Number::Float(f) => return Value::FloatValue(f)
+        }
+    }
+
+    pub fn to_float(&self) -> f64 {
+        match self {
+            Number::Int(i) => return i.to_f64().unwrap_or(f64::NAN),
+            Number::Rational(r) => return r.numer().to_f64().unwrap_or(f64::NAN) / r.denom().to_f64().unwrap_or(f64::NAN),
+            Number::Float(f) => return *f
+        }
+    }
+
+    pub fn to_rational(&self) -> Result<BigRational, Err> {
+        match self {
+            Number::Int(i) => return Ok(BigRational::from_integer(i.clone())),
+            Number::Rational(r) => return Ok(r.clone()),
+            Number::Float(f) => return BigRational::from_float(*f).ok_or(Err::new(format!("Cannot convert {} to a rational", f)))
+        }
+    }
+
+    // Used to break ties between equal numbers of different types in the standard order
+    pub fn type_rank(&self) -> usize {
+        match self {
+            Number::Float(_) => 0,
+            Number::Rational(_) => 1,
+            Number::Int(_) => 2
+        }
+    }
+}
+
+impl std::fmt::Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.clone().into_value())
+    }
+}
+
+enum Promoted {
+    Ints(BigInt, BigInt),
+    Rationals(BigRational, BigRational),
+    Floats(f64, f64)
+}
+
+fn promote(a: Number, b: Number) -> Promoted {
+    match (a, b) {
+        (Number::Int(a), Number::Int(b)) => return Promoted::Ints(a, b),
+        (Number::Float(a), b) => return Promoted::Floats(a, b.to_float()),
+        (a, Number::Float(b)) => return Promoted::Floats(a.to_float(), b),
+        (Number::Rational(a), Number::Int(b)) => return Promoted::Rationals(a, BigRational::from_integer(b)),
+        (Number::Int(a), Number::Rational(b)) => return Promoted::Rationals(BigRational::from_integer(a), b),
+        (Number::Rational(a), Number::Rational(b)) => return Promoted::Rationals(a, b)
+    }
+}
+
+pub fn add(a: Number, b: Number) -> Result<Number, Err> {
+    match promote(a, b) {
+        Promoted::Ints(a, b) => return Ok(Number::Int(a + b)),
+        Promoted::Rationals(a, b) => return Ok(Number::Rational(a + b)),
+        Promoted::Floats(a, b) => return Ok(Number::Float(a + b))
+    }
+}
+
+pub fn sub(a: Number, b: Number) -> Result<Number, Err> {
+    match promote(a, b) {
+        Promoted::Ints(a, b) => return Ok(Number::Int(a - b)),
+        Promoted::Rationals(a, b) => return Ok(Number::Rational(a - b)),
+        Promoted::Floats(a, b) => return Ok(Number::Float(a - b))
+    }
+}
+
+pub fn mul(a: Number, b: Number) -> Result<Number, Err> {
+    match promote(a, b) {
+        Promoted::Ints(a, b) => return Ok(Number::Int(a * b)),
+        Promoted::Rationals(a, b) => return Ok(Number::Rational(a * b)),
+        Promoted::Floats(a, b) => return Ok(Number::Float(a * b))
+    }
+}
+
+// Dividing two integers is still integer division; use rationals or floats for exact results
+pub fn div(a: Number, b: Number) -> Result<Number, Err> {
+    match promote(a, b) {
+        Promoted::Ints(a, b) => {
+            if b.is_zero() {
+                return Err::err_res(format!("Cannot divide {} by zero", a));
+            }
+
+            return Ok(Number::Int(a / b));
+        }
+
+        Promoted::Rationals(a, b) => {
+            if b.is_zero() {
+                return Err::err_res(format!("Cannot divide {} by zero", a));
+            }
+
+            return Ok(Number::Rational(a / b));
+        }
+
+        Promoted::Floats(a, b) => return Ok(Number::Float(a / b))
+    }
+}
+
+pub fn pow(a: Number, b: Number) -> Result<Number, Err> {
+    match (a, b) {
+        (Number::Int(a), Number::Int(b)) => {
+            match b.to_biguint() {
+                Some(exp) => return Ok(Number::Int(a.pow(exp))),
+                None => return Err::err_res(format!("Cannot raise {} to the power of {} because {} is negative", a, b, b))
+            }
+        }
+
+        (Number::Rational(a), Number::Int(b)) => {
+            if a.is_zero() && b < BigInt::zero() {
+                return Err::err_res(format!("Cannot raise zero to the negative power {}", b));
+            }
+
+            let exp = b.abs().to_biguint().unwrap_or_default();
+            let res = BigRational::new(a.numer().pow(exp.clone()), a.denom().pow(exp));
+
+            if b < BigInt::zero() {
+                return Ok(Number::Rational(res.recip()));
+            } else {
+                return Ok(Number::Rational(res));
+            }
+        }
+
+        (a, b) => return Ok(Number::Float(a.to_float().powf(b.to_float())))
+    }
+}
+
+pub fn compare(a: Number, b: Number) -> Option<Ordering> {
+    match promote(a, b) {
+        Promoted::Ints(a, b) => return Some(a.cmp(&b)),
+        Promoted::Rationals(a, b) => return Some(a.cmp(&b)),
+        Promoted::Floats(a, b) => return a.partial_cmp(&b)
+    }
+}
+
+// The order used by the standard order of terms. Unlike compare, it's total: floats are ordered by total_cmp,
+// so NaN is equal to itself (and comes after every other number), and -0.0 comes before 0.0.
+pub fn total_compare(a: Number, b: Number) -> Ordering {
+    match promote(a, b) {
+        Promoted::Ints(a, b) => return a.cmp(&b),
+        Promoted::Rationals(a, b) => return a.cmp(&b),
+        Promoted::Floats(a, b) => return a.total_cmp(&b)
+    }
+}
+
+fn float_to_int(f: f64) -> Result<BigInt, Err> {
+    return BigInt::from_f64(f).ok_or(Err::new(format!("Cannot convert {} to an integer", f)));
+}
+
+pub fn floor(a: Number) -> Result<BigInt, Err> {
+    match a {
+        Number::Int(i) => return Ok(i),
+        Number::Rational(r) => return Ok(r.floor().to_integer()),
+        Number::Float(f) => return float_to_int(f.floor())
+    }
+}
+
+pub fn ceiling(a: Number) -> Result<BigInt, Err> {
+    match a {
+        Number::Int(i) => return Ok(i),
+        Number::Rational(r) => return Ok(r.ceil().to_integer()),
+        Number::Float(f) => return float_to_int(f.ceil())
+    }
+}
+
+// Rounds half away from zero
+pub fn round(a: Number) -> Result<BigInt, Err> {
+    match a {
+        Number::Int(i) => return Ok(i),
+        Number::Rational(r) => return Ok(r.round().to_integer()),
+        Number::Float(f) => return float_to_int(f.round())
+    }
+}
+
+pub fn truncate(a: Number) -> Result<BigInt, Err> {
+    match a {
+        Number::Int(i) => return Ok(i),
+        Number::Rational(r) => return Ok(r.trunc().to_integer()),
+        Number::Float(f) => return float_to_int(f.trunc())
+    }
+}
@@ -0,0 +1,82 @@
+#![allow(clippy::needless_return, clippy::single_match)]
+
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+// Atoms are interned, so comparing two of them only compares their ids.
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Atom(usize);
+
+struct AtomTable {
+    names: Vec<String>,
+    ids: HashMap<String, usize>
+}
+
+fn atom_table() -> &'static RwLock<AtomTable> {
+    static TABLE: OnceLock<RwLock<AtomTable>> = OnceLock::new();
+
+    return TABLE.get_or_init(|| RwLock::new(AtomTable {
+        names: Vec::new(),
+        ids: HashMap::new()
+    }));
+}
+
+impl Atom {
+    pub fn intern(name: &str) -> Atom {
+        match atom_table().read().unwrap().ids.get(name) {
+            Some(id) => return Atom(*id),
+            None => {}
+        }
+
+        let mut table = atom_table().write().unwrap();
+
+        // Someone else may have interned it between releasing the read lock and getting the write lock
+        match table.ids.get(name) {
+            Some(id) => return Atom(*id),
+            None => {}
+        }
+
+        let id = table.names.len();
+        table.names.push(name.to_string());
+        table.ids.insert(name.to_string(), id);
+
+        return Atom(id);
+    }
+
+    pub fn name(&self) -> String {
+        return atom_table().read().unwrap().names[self.0].clone();
+    }
+}
+
+fn is_symbol_char(c: char) -> bool {
+    return "+-*/\\^<>=~:.?@#&$".contains(c);
+}
+
+// Atoms only need quotes if they wouldn't be read back as the same atom, e.g., 'hello world' or 'Foo'
+pub fn quote_atom(name: &str) -> String {
+    let mut chars = name.chars();
+
+    let plain = match chars.next() {
+        Some(c) if c.is_ascii_lowercase() => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        Some(c) if is_symbol_char(c) => chars.all(is_symbol_char),
+        _ => name == "[]" || name == "{}" || name == "!" || name == ";"
+    };
+
+    if plain {
+        return name.to_string();
+    } else {
+        return format!("'{}'", name.replace('\\', "\\\\").replace('\'', "\\'").replace('\n', "\\n").replace('\t', "\\t"));
+    }
+}
+
+impl std::fmt::Display for Atom {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", quote_atom(&self.name()))
+    }
+}
+
+impl std::fmt::Debug for Atom {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Atom({})", self)
+    }
+}
@@ -0,0 +1,74 @@
+#![allow(clippy::needless_return)]
+
+use std::collections::HashMap;
+
+use crate::err::Err;
+use crate::stackitem::{StackItem, Value};
+
+// Facts asserted by the program, grouped by name and arity. The database lives outside of the Environment,
+// so backtracking doesn't undo changes to it.
+#[derive(Clone)]
+pub struct Database {
+    clauses: HashMap<(String, usize), Vec<StackItem>>
+}
+
+pub fn clause_key(term: &StackItem) -> Result<(String, usize), Err> {
+    match term {
+        StackItem::Value(Value::Atom(atom)) => return Ok((atom.name(), 0)),
+        StackItem::Value(Value::Functor(name, args)) => return Ok((name.clone(), args.len())),
+        StackItem::Variable(var_name) => return Err::err_res(format!("Clauses must be atoms or functors, but got unbound variable: {}", var_name)),
+        StackItem::Value(c) => return Err::err_res(format!("Clauses must be atoms or functors, but got: {}", c))
+    }
+}
+
+// Renames variables to $0, $1, ... in order of appearance. No instruction can create variables with these
+// names, so a stored term never shares variables with the program.
+pub fn number_vars(item: &StackItem, mapping: &mut HashMap<String, String>) -> StackItem {
+    match item {
+        StackItem::Variable(var_name) => {
+            let next = format!("${}", mapping.len());
+            return StackItem::Variable(mapping.entry(var_name.clone()).or_insert(next).clone());
+        }
+
+        StackItem::Value(Value::Functor(name, args)) => {
+            let new_args = args.iter().map(|arg| number_vars(arg, mapping)).collect();
+            return StackItem::Value(Value::Functor(name.clone(), new_args));
+        }
+
+        _ => return item.clone()
+    }
+}
+
+impl Database {
+    pub fn new() -> Database {
+        Database {
+            clauses: HashMap::new()
+        }
+    }
+
+    // The term should already be resolved, so any variables left in it are unbound
+    pub fn assert(&mut self, term: &StackItem) -> Result<(), Err> {
+        let key = clause_key(term)?;
+        let stored = number_vars(term, &mut HashMap::new());
+
+        self.clauses.entry(key).or_default().push(stored);
+
+        return Ok(());
+    }
+
+    // Returns a snapshot of the clauses, so that changes made while iterating over them aren't seen
+    // (the "logical update view").
+    pub fn clauses(&self, key: &(String, usize)) -> Vec<StackItem> {
+        return self.clauses.get(key).cloned().unwrap_or_default();
+    }
+
+    pub fn remove(&mut self, key: &(String, usize), idx: usize) {
+        match self.clauses.get_mut(key) {
+            Some(clauses) if idx < clauses.len() => {
+                clauses.remove(idx);
+            }
+
+            _ => {}
+        }
+    }
+}
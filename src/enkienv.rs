@@ -1,21 +1,61 @@
+#![allow(clippy::needless_return, clippy::single_match, clippy::ptr_arg)]
+
+use std::cmp::Ordering;
 use std::collections::HashSet;
 use std::collections::HashMap;
 use std::collections::VecDeque;
+use std::io::Write;
 
 use num_bigint::BigInt;
-use num_traits::pow::Pow;
+use num_rational::BigRational;
 use num_traits::ToPrimitive;
 
+use crate::arith;
+use crate::arith::Number;
 use crate::err::Err;
-use crate::stackitem::{StackItem, Value};
+use crate::fd::{Constraint, Domain};
+use crate::stackitem::{StackItem, Value, CONS, EMPTY, empty_list, make_list};
 use crate::unification::Unification;
 
+// Marks a saved choicepoint whose unifications have been garbage collected. Choicepoints don't change while
+// they're saved, so they only need to be collected once. The mark has to be cleared when a saved environment
+// becomes the current one again.
+#[derive(Clone, Debug, Default)]
+pub struct GcMark(bool);
+
+// Set by catch. If a resource error happens, the saved state is restored and execution continues at idx.
+// Frames belong to the VM, not the environment, so we only remember how many there were.
+#[derive(Clone, Debug)]
+pub struct Handler {
+    pub idx: usize,
+    pub frames: usize,
+    pub saved: Box<Environment> // Its own handler is the one to put back after this one
+}
+
 #[derive(Clone, Debug)]
 pub struct Environment {
     pub data: VecDeque<StackItem>,
     pub unified: HashMap<String, Unification>,
-    pub choicepoint: Option<(usize, Box<Environment>)>,
-    pub fresh_counter: usize
+    pub choicepoint: Option<(usize, Box<Environment>)>, // Set with set_choicepoint, to keep choicepoint_count right
+    pub choicepoint_count: usize, // How many choicepoints are in the chain, so limits can check it cheaply
+    pub fresh_counter: usize,
+    pub fresh_names: HashSet<String>, // Names made by fresh_var that may still be in use, see compact_unified
+    pub constraints: Vec<Constraint>,
+    pub propagate_pending: bool,
+    pub attr_hook: Option<usize>,
+    pub pending_wakeups: Vec<(StackItem, StackItem)>, // Attribute, and what the variable was bound to
+    pub globals: HashMap<String, (u64, StackItem)>, // Set by bset with its generation, so backtracking restores the old values
+    pub handler: Option<Handler>,
+
+    // How deep we are in the search tree, counting choices made and jumps taken on the way here
+    pub depth: usize,
+    pub calls: usize,
+
+    pub collected: GcMark
+}
+
+fn write_err(err: std::io::Error) -> Err {
+    return Err::new(format!("Could not write output: {}", err));
 }
 
 impl Environment {
@@ -24,10 +64,49 @@ impl Environment {
             data: VecDeque::new(),
             unified: HashMap::new(),
             choicepoint: None,
-            fresh_counter: 0
+            choicepoint_count: 0,
+            fresh_counter: 0,
+            fresh_names: HashSet::new(),
+            constraints: Vec::new(),
+            propagate_pending: false,
+            attr_hook: None,
+            pending_wakeups: Vec::new(),
+            globals: HashMap::new(),
+            handler: None,
+            depth: 0,
+            calls: 0,
+            collected: GcMark::default()
         }
     }
 
+    // Restores the state saved in the most recent choicepoint, returning the index to resume execution at.
+    // The fresh counter is kept so that variables created after the choicepoint are never reused.
+    pub fn backtrack(&mut self) -> Option<usize> {
+        let (idx, saved) = self.take_choicepoint()?;
+        let fresh_counter = self.fresh_counter.max(saved.fresh_counter);
+
+        *self = *saved;
+        self.fresh_counter = fresh_counter;
+        self.collected = GcMark::default();
+
+        return Some(idx);
+    }
+
+    pub fn set_choicepoint(&mut self, choicepoint: Option<(usize, Box<Environment>)>) {
+        self.choicepoint_count = match &choicepoint {
+            Some((_, saved)) => saved.choicepoint_count + 1,
+            None => 0
+        };
+
+        self.choicepoint = choicepoint;
+    }
+
+    pub fn take_choicepoint(&mut self) -> Option<(usize, Box<Environment>)> {
+        self.choicepoint_count = 0;
+
+        return self.choicepoint.take();
+    }
+
     pub fn destroy(&mut self) -> Result<(), Err> {
         match self.pop()? {
             StackItem::Variable(var_name) => {
@@ -40,14 +119,97 @@ impl Environment {
         return Ok(());
     }
 
-    pub fn print_stack(&mut self) -> Result<(), Err> {
-        println!("{:?}", self.data);
-        return Ok(());
+    // Removes the unifications of fresh variables that can't be reached from the stack, the globals, the
+    // constraints, or the given roots, in this environment and in every choicepoint saved before it.
+    pub fn collect_garbage(&mut self, roots: &[String]) {
+        self.compact_unified(roots);
+
+        let mut cur = self.choicepoint.as_mut();
+
+        while let Some((_, saved)) = cur {
+            // Everything from here down was already collected by an earlier pass
+            if saved.collected.0 {
+                return;
+            }
+
+            saved.compact_unified(roots);
+            saved.collected = GcMark(true);
+
+            cur = saved.choicepoint.as_mut();
+        }
     }
 
-    pub fn print_unification(&mut self) -> Result<(), Err> {
-        println!("{:?}", self.unified);
-        return Ok(());
+    fn compact_unified(&mut self, roots: &[String]) {
+        // Names that fresh_var didn't make come from the program, which can mention them again at any time, so
+        // only fresh variables are ever collected. Names are recorded rather than recognized, since the program
+        // can also use names that look like fresh ones.
+        let mut to_visit = roots.to_vec();
+        to_visit.extend(self.unified.keys().filter(|var_name| !self.fresh_names.contains(*var_name)).cloned());
+
+        for item in self.data.iter().chain(self.globals.values().map(|(_, item)| item)) {
+            item.vars(&mut to_visit);
+        }
+
+        for constraint in &self.constraints {
+            for item in constraint.items() {
+                item.vars(&mut to_visit);
+            }
+        }
+
+        for (attribute, bound_to) in &self.pending_wakeups {
+            attribute.vars(&mut to_visit);
+            bound_to.vars(&mut to_visit);
+        }
+
+        // Anything a live variable is unified with is live too. Disunifications don't keep variables alive:
+        // a variable that can't be reached can never be unified with anything again.
+        let mut live = HashSet::new();
+
+        while let Some(var_name) = to_visit.pop() {
+            if live.contains(&var_name) {
+                continue;
+            }
+
+            match self.unified.get(&var_name) {
+                Some(unification) => {
+                    to_visit.extend(unification.var_unify.iter().cloned());
+
+                    for c in unification.value_unify.iter().chain(unification.value_disunify.iter()) {
+                        StackItem::Value(c.clone()).vars(&mut to_visit);
+                    }
+
+                    match &unification.attribute {
+                        Some(attribute) => attribute.vars(&mut to_visit),
+                        None => {}
+                    }
+                }
+
+                None => {}
+            }
+
+            live.insert(var_name);
+        }
+
+        self.unified.retain(|var_name, _| live.contains(var_name));
+
+        // Names that aren't live can never be mentioned again, and fresh_var never makes them again either
+        self.fresh_names.retain(|var_name| live.contains(var_name));
+        self.fresh_names.shrink_to_fit();
+
+        for unification in self.unified.values_mut() {
+            unification.var_disunify.retain(|var_name| live.contains(var_name));
+        }
+
+        self.unified.shrink_to_fit();
+    }
+
+    pub fn print_stack(&mut self, out: &mut dyn Write) -> Result<(), Err> {
+        let data = self.resolved_data()?;
+        return writeln!(out, "{:?}", data).map_err(write_err);
+    }
+
+    pub fn print_unification(&mut self, out: &mut dyn Write) -> Result<(), Err> {
+        return writeln!(out, "{:?}", self.unified).map_err(write_err);
     }
 
     pub fn swap(&mut self) -> Result<(), Err> {
@@ -60,19 +222,103 @@ impl Environment {
         return Ok(());
     }
 
-    pub fn print(&mut self) -> Result<(), Err> {
-        match self.pop()? {
-            StackItem::Variable(var_name) => {
-                match self.var_value_opt(&var_name)? {
-                    Some(val) => print!("{}", val),
-                    None => print!("{}", var_name)
+    pub fn print(&mut self, out: &mut dyn Write) -> Result<(), Err> {
+        let item = self.pop()?;
+        let resolved = self.resolve_item(&item)?;
+
+        return write!(out, "{}", resolved).map_err(write_err);
+    }
+
+    // Like print, but quotes strings and atoms where needed so the output can be read back in
+    pub fn writeq(&mut self, out: &mut dyn Write) -> Result<(), Err> {
+        let item = self.pop()?;
+        let resolved = self.resolve_item(&item)?;
+
+        return write!(out, "{}", resolved.quoted()).map_err(write_err);
+    }
+
+    // Pops a format string, then one argument for each directive that needs one, in order:
+    //     ~w writes a term without quotes, ~q writes it quoted, ~d writes an integer, ~s writes a string, ~a writes an atom,
+    //     ~n writes a newline, and ~~ writes a tilde.
+    // Nothing is written unless the whole string can be formatted.
+    pub fn format(&mut self, out: &mut dyn Write) -> Result<(), Err> {
+        let fmt = self.pop_string()?;
+
+        let mut res = String::new();
+        let mut chars = fmt.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '~' {
+                res.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('n') => res.push('\n'),
+                Some('~') => res.push('~'),
+                Some('w') => {
+                    let item = self.pop()?;
+                    res.push_str(&self.resolve_item(&item)?.plain());
                 }
-            },
+                Some('q') => {
+                    let item = self.pop()?;
+                    res.push_str(&self.resolve_item(&item)?.quoted());
+                }
+                Some('d') => {
+                    let item = self.pop()?;
 
-            StackItem::Value(val) => print!("{}", val)
+                    match self.item_value(&item)? {
+                        Some(Value::IntValue(i)) => res.push_str(&i.to_string()),
+                        _ => return Err::err_res(format!("~d expects an integer, but got: {}", self.resolve_item(&item)?))
+                    }
+                }
+                Some('s') => {
+                    let s = self.pop_string()?;
+                    res.push_str(&s);
+                }
+                Some('a') => {
+                    let item = self.pop()?;
+
+                    match self.item_value(&item)? {
+                        Some(Value::Atom(atom)) => res.push_str(&atom.name()),
+                        _ => return Err::err_res(format!("~a expects an atom, but got: {}", self.resolve_item(&item)?))
+                    }
+                }
+                Some(d) => return Err::err_res(format!("Unknown format directive ~{} in: {}", d, fmt)),
+                None => return Err::err_res(format!("Format string ends with an incomplete directive: {}", fmt))
+            }
         }
 
-        return Ok(());
+        return write!(out, "{}", res).map_err(write_err);
+    }
+
+    // Substitutes the values of all bound variables, including those nested inside functors
+    pub fn resolve_item(&self, item: &StackItem) -> Result<StackItem, Err> {
+        match self.item_value(item)? {
+            Some(Value::Functor(name, args)) => {
+                let mut new_args = Vec::new();
+
+                for arg in &args {
+                    new_args.push(self.resolve_item(arg)?);
+                }
+
+                return Ok(StackItem::Value(Value::Functor(name, new_args)));
+            }
+
+            Some(c) => return Ok(StackItem::Value(c)),
+            None => return Ok(item.clone())
+        }
+    }
+
+    pub fn resolved_data(&self) -> Result<Vec<StackItem>, Err> {
+        return self.data.iter().map(|item| self.resolve_item(item)).collect();
+    }
+
+    pub fn resolve(&mut self) -> Result<(), Err> {
+        let item = self.pop()?;
+        let resolved = self.resolve_item(&item)?;
+
+        return self.push(resolved);
     }
 
     pub fn push(&mut self, new_item: StackItem) -> Result<(), Err> {
@@ -106,11 +352,20 @@ impl Environment {
         return self.var_value_opt(var_name)?.ok_or(Err::new(format!("No value found for: {}", var_name)));
     }
 
+    pub fn fresh_var(&mut self) -> StackItem {
+        let fresh_var_name = format!("T_{}", self.fresh_counter);
+        self.fresh_counter += 1;
+        self.fresh_names.insert(fresh_var_name.clone());
+
+        return StackItem::Variable(fresh_var_name);
+    }
+
     pub fn functor(&mut self) -> Result<(), Err> {
         let mut items = Vec::new();
 
         let name = match self.pop()? {
             StackItem::Value(Value::StringValue(s)) => s,
+            StackItem::Variable(var_name) => return self.functor_term(StackItem::Variable(var_name)),
             item => return Err::err_res(format!("Functor name must be a string. Got: {:?}", item))
         };
 
@@ -174,98 +429,104 @@ impl Environment {
         return self.popint()?.to_usize().ok_or(Err::new("".to_string()));
     }
 
-    pub fn add(&mut self) -> Result<(), Err> {
-        let a = self.popint()?;
-        let b = self.popint()?;
-
-        self.push(StackItem::Value(Value::IntValue(a + b)))?;
+    pub fn popnum(&mut self) -> Result<Number, Err> {
+        let item = self.pop()?;
 
-        return Ok(());
+        match self.item_value(&item)? {
+            Some(c) => return Number::from_value(&c).ok_or(Err::new(format!("Top stack item was not a number: {}", c))),
+            None => return Err::err_res(format!("Top stack item was not a number: {}", item))
+        }
     }
 
-    pub fn sub(&mut self) -> Result<(), Err> {
-        let a = self.popint()?;
-        let b = self.popint()?;
+    fn arith_op(&mut self, op: fn(Number, Number) -> Result<Number, Err>) -> Result<(), Err> {
+        let a = self.popnum()?;
+        let b = self.popnum()?;
 
-        self.push(StackItem::Value(Value::IntValue(a - b)))?;
+        let res = op(a, b)?;
 
-        return Ok(());
+        return self.push(StackItem::Value(res.into_value()));
     }
 
-    pub fn div(&mut self) -> Result<(), Err> {
-        let a = self.popint()?;
-        let b = self.popint()?;
+    pub fn add(&mut self) -> Result<(), Err> {
+        return self.arith_op(arith::add);
+    }
 
-        self.push(StackItem::Value(Value::IntValue(a / b)))?;
+    pub fn sub(&mut self) -> Result<(), Err> {
+        return self.arith_op(arith::sub);
+    }
 
-        return Ok(());
+    pub fn div(&mut self) -> Result<(), Err> {
+        return self.arith_op(arith::div);
     }
 
     pub fn mul(&mut self) -> Result<(), Err> {
-        let a = self.popint()?;
-        let b = self.popint()?;
-
-        self.push(StackItem::Value(Value::IntValue(a * b)))?;
+        return self.arith_op(arith::mul);
+    }
 
-        return Ok(());
+    pub fn pow(&mut self) -> Result<(), Err> {
+        return self.arith_op(arith::pow);
     }
 
-    pub fn lt(&mut self) -> Result<(), Err> {
-        let a = self.popint()?;
-        let b = self.popint()?;
+    fn compare_op(&mut self, relation: &str, test: fn(Ordering) -> bool) -> Result<(), Err> {
+        let a = self.popnum()?;
+        let b = self.popnum()?;
 
-        if a < b {
-            return Ok(());
-        } else {
-            return Err::err_res(format!("{} not less than {}", a, b));
+        match arith::compare(a.clone(), b.clone()) {
+            Some(ord) if test(ord) => return Ok(()),
+            _ => return Err::err_res(format!("{} not {} {}", a, relation, b))
         }
     }
 
-    pub fn gt(&mut self) -> Result<(), Err> {
-        let a = self.popint()?;
-        let b = self.popint()?;
+    pub fn lt(&mut self) -> Result<(), Err> {
+        return self.compare_op("less than", |ord| ord == Ordering::Less);
+    }
 
-        if a > b {
-            return Ok(());
-        } else {
-            return Err::err_res(format!("{} not less than {}", a, b));
-        }
+    pub fn gt(&mut self) -> Result<(), Err> {
+        return self.compare_op("greater than", |ord| ord == Ordering::Greater);
     }
 
     pub fn lte(&mut self) -> Result<(), Err> {
-        let a = self.popint()?;
-        let b = self.popint()?;
-
-        if a <= b {
-            return Ok(());
-        } else {
-            return Err::err_res(format!("{} not less than {}", a, b));
-        }
+        return self.compare_op("less than or equal to", |ord| ord != Ordering::Greater);
     }
 
     pub fn gte(&mut self) -> Result<(), Err> {
-        let a = self.popint()?;
-        let b = self.popint()?;
+        return self.compare_op("greater than or equal to", |ord| ord != Ordering::Less);
+    }
 
-        if a >= b {
-            return Ok(());
-        } else {
-            return Err::err_res(format!("{} not less than {}", a, b));
-        }
+    fn convert_op(&mut self, op: fn(Number) -> Result<BigInt, Err>) -> Result<(), Err> {
+        let a = self.popnum()?;
+        let res = op(a)?;
+
+        return self.push(StackItem::Value(Value::IntValue(res)));
     }
 
-    pub fn pow(&mut self) -> Result<(), Err> {
-        let a = self.popint()?;
-        let bint = self.popint()?;
+    pub fn floor(&mut self) -> Result<(), Err> {
+        return self.convert_op(arith::floor);
+    }
 
-        return match bint.to_biguint() {
-            Some(b) => {
-                self.push(StackItem::Value(Value::IntValue(a.pow(b))))?;
+    pub fn ceiling(&mut self) -> Result<(), Err> {
+        return self.convert_op(arith::ceiling);
+    }
 
-                Ok(())
-            }
-            None => Err::err_res(format!("Cannot raise {} to the power of {} because {} is negative", a, bint, bint))
-        };
+    pub fn round(&mut self) -> Result<(), Err> {
+        return self.convert_op(arith::round);
+    }
+
+    pub fn truncate(&mut self) -> Result<(), Err> {
+        return self.convert_op(arith::truncate);
+    }
+
+    pub fn tofloat(&mut self) -> Result<(), Err> {
+        let a = self.popnum()?;
+
+        return self.push(StackItem::Value(Value::FloatValue(a.to_float())));
+    }
+
+    pub fn torational(&mut self) -> Result<(), Err> {
+        let a = self.popnum()?;
+        let r: BigRational = a.to_rational()?;
+
+        return self.push(StackItem::Value(Number::Rational(r).into_value()));
     }
 
     pub fn project(&mut self) -> Result<(), Err> {
@@ -303,26 +564,20 @@ impl Environment {
 
         let mut checked = HashSet::new();
 
-        loop {
-            match to_check.pop_front() {
-                Some(var_name) => {
-                    if checked.contains(var_name) {
-                        continue;
-                    }
-                    checked.insert(var_name);
-
-                    let unification = self.get_unified(var_name)?;
-
-                    match action(unification)? {
-                        Some(res) => return Ok(res),
-                        None => {}
-                    }
+        while let Some(var_name) = to_check.pop_front() {
+            if checked.contains(var_name) {
+                continue;
+            }
+            checked.insert(var_name);
 
-                    to_check.extend(&unification.var_unify);
-                },
+            let unification = self.get_unified(var_name)?;
 
-                None => break
+            match action(unification)? {
+                Some(res) => return Ok(res),
+                None => {}
             }
+
+            to_check.extend(&unification.var_unify);
         }
 
         return default;
@@ -348,6 +603,13 @@ impl Environment {
                     }
                 }
 
+                // Binding to c fails if we're disunified with a variable that's already bound to c
+                for other in &unification.var_disunify {
+                    if self.var_value_opt(other)?.as_ref() == Some(c) {
+                        return Ok(Some(true));
+                    }
+                }
+
                 match &unification.value_unify {
                     Some(cur_c) => {
                         return Ok(Some(cur_c != c));
@@ -423,11 +685,30 @@ impl Environment {
                     return Ok(());
                 }
 
-                _ => {}
+                (Some(c1), Some(c2)) if c1 != c2 => {
+                    return Err::err_res(format!("Cannot unify {} and {}: values '{}' and '{}' don't match", v1, v2, c1, c2));
+                }
+
+                (val1, val2) => {
+                    // Binding an unbound attributed variable (to either a value or another variable) wakes it up
+                    if val1.is_none() {
+                        let bound_to = val2.clone().map_or(StackItem::Variable(v2.clone()), StackItem::Value);
+                        self.wake_attributes(&v1, bound_to);
+                    }
+
+                    if val2.is_none() {
+                        let bound_to = val1.map_or(StackItem::Variable(v1.clone()), StackItem::Value);
+                        self.wake_attributes(&v2, bound_to);
+                    }
+                }
             }
 
             self.unify_with(&v1, &v2)?;
             self.unify_with(&v2, &v1)?;
+
+            self.propagate_pending = true;
+
+            return self.check_domain(&v1);
         }
 
         return Ok(());
@@ -456,10 +737,16 @@ impl Environment {
             return Err::err_res(format!("Could not unify '{}' and '{}'", v, c));
         }
 
+        if self.var_value_opt(&v)?.is_none() {
+            self.wake_attributes(&v, StackItem::Value(c.clone()));
+        }
+
         let unified = self.access_unified(&v);
         unified.value_unify = Some(c);
 
-        return Ok(());
+        self.propagate_pending = true;
+
+        return self.check_domain(&v);
     }
 
     fn unify_items(&mut self, item1: StackItem, item2: StackItem) -> Result<(), Err> {
@@ -501,7 +788,7 @@ impl Environment {
     }
 
     fn disunify_with(&mut self, v1: &String, v2: &String) -> Result<(), Err> {
-        if self.is_unified(v1, v2)? {
+        if v1 == v2 || self.is_unified(v1, v2)? {
             return Err::err_res(format!("Could not disunify '{}' and '{}'", v1, v2));
         }
 
@@ -511,32 +798,20 @@ impl Environment {
         return Ok(());
     }
 
+    // A bound variable is disunified by its value, so two variables bound to equal values can't be disunified
     fn disunify_vars(&mut self, v1: String, v2: String) -> Result<(), Err> {
-        if v1 != v2 {
-            self.ensure_unification_exists(&v1)?;
-            self.ensure_unification_exists(&v2)?;
-
-            match (self.var_value_opt(&v1)?, self.var_value_opt(&v2)?) {
-                (Some(Value::Functor(name1, args1)), Some(Value::Functor(name2, args2))) => {
-                    if name1 != name2 {
-                        return Ok(());
-                    }
-
-                    for (arg1, arg2) in args1.iter().zip(args2.iter()) {
-                        self.disunify_items(arg1.clone(), arg2.clone())?;
-                    }
-
-                    return Ok(());
-                }
-
-                _ => {}
+        self.ensure_unification_exists(&v1)?;
+        self.ensure_unification_exists(&v2)?;
+
+        match (self.var_value_opt(&v1)?, self.var_value_opt(&v2)?) {
+            (Some(c1), Some(c2)) => return self.disunify_items(StackItem::Value(c1), StackItem::Value(c2)),
+            (Some(c1), None) => return self.disunify_var_value(v2, c1),
+            (None, Some(c2)) => return self.disunify_var_value(v1, c2),
+            (None, None) => {
+                self.disunify_with(&v1, &v2)?;
+                return self.disunify_with(&v2, &v1);
             }
-
-            self.disunify_with(&v1, &v2)?;
-            self.disunify_with(&v2, &v1)?;
         }
-
-        return Ok(());
     }
 
     fn disunify_var_value(&mut self, v: String, c: Value) -> Result<(), Err> {
@@ -565,7 +840,9 @@ impl Environment {
         let unified = self.access_unified(&v);
         unified.value_disunify.push(c);
 
-        return Ok(());
+        self.propagate_pending = true;
+
+        return self.check_domain(&v);
     }
 
     pub fn disunify_items(&mut self, item1: StackItem, item2: StackItem) -> Result<(), Err> {
@@ -605,4 +882,1184 @@ impl Environment {
 
         return self.disunify_items(item1, item2);
     }
+
+    fn alias_class(&self, v: &String) -> Vec<String> {
+        let mut class = Vec::new();
+        let mut to_check = vec![v.clone()];
+        let mut checked = HashSet::new();
+
+        while let Some(var_name) = to_check.pop() {
+            if !checked.insert(var_name.clone()) {
+                continue;
+            }
+
+            match self.unified.get(&var_name) {
+                Some(unification) => to_check.extend(unification.var_unify.iter().cloned()),
+                None => {}
+            }
+
+            class.push(var_name);
+        }
+
+        return class;
+    }
+
+    // The domain of a variable is the intersection of the domains of everything it's unified with,
+    // minus any integers it has been disunified with.
+    fn var_domain(&self, v: &String) -> Option<Domain> {
+        let mut domain: Option<Domain> = None;
+        let mut excluded = Vec::new();
+
+        for var_name in self.alias_class(v) {
+            match self.unified.get(&var_name) {
+                Some(unification) => {
+                    match &unification.domain {
+                        Some(d) => {
+                            domain = Some(match domain {
+                                Some(cur) => cur.intersect(d),
+                                None => d.clone()
+                            });
+                        }
+
+                        None => {}
+                    }
+
+                    for c in &unification.value_disunify {
+                        match c {
+                            Value::IntValue(i) => excluded.push(i.clone()),
+                            _ => {}
+                        }
+                    }
+                }
+
+                None => {}
+            }
+        }
+
+        return domain.map(|d| excluded.iter().fold(d, |d, i| d.remove(i)));
+    }
+
+    // Makes sure a variable's value (if any) is in its domain. If the domain has been narrowed down to a
+    // single value, the variable is bound to it.
+    fn check_domain(&mut self, v: &String) -> Result<(), Err> {
+        let domain = match self.var_domain(v) {
+            Some(domain) => domain,
+            None => return Ok(())
+        };
+
+        if domain.is_empty() {
+            return Err::err_res(format!("No values left in the domain of {}", v));
+        }
+
+        match self.var_value_opt(v)? {
+            Some(Value::IntValue(i)) => {
+                if !domain.contains(&i) {
+                    return Err::err_res(format!("Value {} of {} is not in its domain {}", i, v, domain));
+                }
+            }
+
+            Some(c) => return Err::err_res(format!("Variable {} has a finite domain, but is bound to non-integer '{}'", v, c)),
+
+            None => {
+                match domain.single_value() {
+                    Some(i) => self.unify_var_value(v.clone(), Value::IntValue(i))?,
+                    None => {}
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn set_var_domain(&mut self, v: &String, domain: Domain) -> Result<(), Err> {
+        self.ensure_unification_exists(v)?;
+
+        if self.var_domain(v).as_ref() == Some(&domain) {
+            return Ok(());
+        }
+
+        for var_name in self.alias_class(v) {
+            self.access_unified(&var_name).domain = Some(domain.clone());
+        }
+
+        self.propagate_pending = true;
+
+        return self.check_domain(v);
+    }
+
+    fn item_value(&self, item: &StackItem) -> Result<Option<Value>, Err> {
+        match item {
+            StackItem::Value(c) => return Ok(Some(c.clone())),
+            StackItem::Variable(var_name) => {
+                if self.unified.contains_key(var_name) {
+                    return self.var_value_opt(var_name);
+                } else {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    // Returns None if the item is an unconstrained variable
+    fn item_domain(&self, item: &StackItem) -> Result<Option<Domain>, Err> {
+        match self.item_value(item)? {
+            Some(Value::IntValue(i)) => return Ok(Some(Domain::singleton(i))),
+            Some(c) => return Err::err_res(format!("Expected an integer in finite domain constraint, but got: {}", c)),
+            None => {
+                match item {
+                    StackItem::Variable(var_name) => return Ok(self.var_domain(var_name)),
+                    StackItem::Value(_) => return Ok(None)
+                }
+            }
+        }
+    }
+
+    fn restrict_item(&mut self, item: &StackItem, domain: Domain) -> Result<(), Err> {
+        let new_domain = match self.item_domain(item)? {
+            Some(old_domain) => {
+                let new_domain = old_domain.intersect(&domain);
+
+                if new_domain == old_domain {
+                    return Ok(());
+                }
+
+                new_domain
+            }
+
+            None => domain
+        };
+
+        if new_domain.is_empty() {
+            return Err::err_res(format!("{} has no values left in its domain", item));
+        }
+
+        match item {
+            StackItem::Variable(var_name) => return self.set_var_domain(var_name, new_domain),
+            StackItem::Value(c) => return Err::err_res(format!("{} is not in the domain {}", c, new_domain))
+        }
+    }
+
+    fn parse_domain(&self, item: &StackItem) -> Result<Domain, Err> {
+        let mut bounds = Vec::new();
+
+        let (name, args) = match self.item_value(item)? {
+            Some(Value::IntValue(i)) => return Ok(Domain::singleton(i)),
+            Some(Value::Functor(name, args)) => (name, args),
+            _ => return Err::err_res(format!("Domain must be an integer, interval(Lo, Hi), or set(...). Got: {}", item))
+        };
+
+        for arg in &args {
+            match self.item_value(arg)? {
+                Some(Value::IntValue(i)) => bounds.push(i),
+                _ => return Err::err_res(format!("Domain bounds must be integers. Got: {}", arg))
+            }
+        }
+
+        if name == "interval" && bounds.len() == 2 {
+            let hi = bounds.pop();
+            let lo = bounds.pop();
+            return Ok(Domain::interval(lo, hi));
+        } else if name == "set" {
+            return Ok(Domain::Set(bounds.into_iter().collect()));
+        } else {
+            return Err::err_res(format!("Unknown domain: {}", item));
+        }
+    }
+
+    fn propagate_constraint(&mut self, constraint: &Constraint) -> Result<(), Err> {
+        match constraint {
+            Constraint::Eq(a, b) => {
+                match (self.item_domain(a)?, self.item_domain(b)?) {
+                    (Some(da), Some(db)) => {
+                        let domain = da.intersect(&db);
+                        self.restrict_item(a, domain.clone())?;
+                        self.restrict_item(b, domain)?;
+                    }
+
+                    (Some(da), None) => self.restrict_item(b, da)?,
+                    (None, Some(db)) => self.restrict_item(a, db)?,
+                    (None, None) => {}
+                }
+            }
+
+            Constraint::Lt(a, b) => {
+                match self.item_domain(b)?.and_then(|db| db.max()) {
+                    Some(max) => self.restrict_item(a, Domain::interval(None, Some(max - 1)))?,
+                    None => {}
+                }
+
+                match self.item_domain(a)?.and_then(|da| da.min()) {
+                    Some(min) => self.restrict_item(b, Domain::interval(Some(min + 1), None))?,
+                    None => {}
+                }
+            }
+
+            Constraint::AllDifferent(items) => {
+                for (idx, item) in items.iter().enumerate() {
+                    match self.item_domain(item)?.and_then(|d| d.single_value()) {
+                        Some(i) => {
+                            let without = Domain::interval(None, None).remove(&i);
+
+                            for (other_idx, other) in items.iter().enumerate() {
+                                if other_idx != idx {
+                                    self.restrict_item(other, without.clone())?;
+                                }
+                            }
+                        }
+
+                        None => {}
+                    }
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    // Runs all constraints until no domains change.
+    pub fn propagate(&mut self) -> Result<(), Err> {
+        while self.propagate_pending {
+            self.propagate_pending = false;
+
+            for constraint in self.constraints.clone() {
+                self.propagate_constraint(&constraint)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn post(&mut self, constraint: Constraint) -> Result<(), Err> {
+        self.constraints.push(constraint);
+        self.propagate_pending = true;
+
+        return Ok(());
+    }
+
+    pub fn fd_in(&mut self) -> Result<(), Err> {
+        let item = self.pop()?;
+        let domain_item = self.pop()?;
+
+        let domain = self.parse_domain(&domain_item)?;
+
+        return self.restrict_item(&item, domain);
+    }
+
+    pub fn fd_eq(&mut self) -> Result<(), Err> {
+        let a = self.pop()?;
+        let b = self.pop()?;
+
+        return self.post(Constraint::Eq(a, b));
+    }
+
+    pub fn fd_lt(&mut self) -> Result<(), Err> {
+        let a = self.pop()?;
+        let b = self.pop()?;
+
+        return self.post(Constraint::Lt(a, b));
+    }
+
+    pub fn all_different(&mut self) -> Result<(), Err> {
+        let num = self.popidx()?;
+        let mut items = Vec::new();
+
+        for _i in 0..num {
+            items.push(self.pop()?);
+        }
+
+        return self.post(Constraint::AllDifferent(items));
+    }
+
+    // Binds the top item to the smallest value in its domain, adding a choicepoint which will retry
+    // this instruction (at retry_idx) with that value removed from the domain.
+    pub fn label(&mut self, retry_idx: usize) -> Result<(), Err> {
+        let item = self.pop()?;
+
+        let domain = match self.item_domain(&item)? {
+            Some(domain) => domain,
+            None => return Err::err_res(format!("Cannot label {}: it has no domain", item))
+        };
+
+        let min = domain.min().ok_or(Err::new(format!("Cannot label {}: its domain {} has no lower bound", item, domain)))?;
+
+        match &item {
+            StackItem::Variable(var_name) if domain.single_value().is_none() => {
+                self.depth += 1;
+                let mut alt = self.clone();
+                alt.push(item.clone())?;
+
+                if alt.set_var_domain(var_name, domain.remove(&min)).and_then(|_| alt.propagate()).is_ok() {
+                    self.set_choicepoint(Some((retry_idx, Box::new(alt))));
+                }
+            }
+
+            _ => {}
+        }
+
+        return self.restrict_item(&item, Domain::singleton(min));
+    }
+
+    fn wake_attributes(&mut self, v: &String, bound_to: StackItem) {
+        for var_name in self.alias_class(v) {
+            match self.unified.get(&var_name).and_then(|unification| unification.attribute.clone()) {
+                Some(attribute) => self.pending_wakeups.push((attribute, bound_to.clone())),
+                None => {}
+            }
+        }
+    }
+
+    // If any attributed variables were bound, sets up the stack to run the attribute hook once per binding,
+    // returning the index of the hook. Each call of the hook sees the attribute on top of the stack, then
+    // the value the variable was bound to, then the index to go to when it's done.
+    pub fn wake(&mut self, resume_idx: usize) -> Result<Option<usize>, Err> {
+        if self.pending_wakeups.is_empty() {
+            return Ok(None);
+        }
+
+        let wakeups = std::mem::take(&mut self.pending_wakeups);
+
+        let hook = match self.attr_hook {
+            Some(hook) => hook,
+            None => return Ok(None)
+        };
+
+        let mut return_idx = resume_idx;
+
+        for (attribute, bound_to) in wakeups.into_iter().rev() {
+            self.push(StackItem::Value(Value::IntValue(BigInt::from(return_idx))))?;
+            self.push(bound_to)?;
+            self.push(attribute)?;
+
+            return_idx = hook;
+        }
+
+        return Ok(Some(hook));
+    }
+
+    pub fn attrhook(&mut self) -> Result<(), Err> {
+        self.attr_hook = Some(self.popidx()?);
+
+        return Ok(());
+    }
+
+    pub fn putattr(&mut self) -> Result<(), Err> {
+        let var_name = match self.pop()? {
+            StackItem::Variable(var_name) => var_name,
+            item => return Err::err_res(format!("Cannot put an attribute on a non-variable: {}", item))
+        };
+
+        let attribute = self.pop()?;
+
+        self.ensure_unification_exists(&var_name)?;
+
+        match self.var_value_opt(&var_name)? {
+            Some(c) => return Err::err_res(format!("Cannot put an attribute on {}, it's already bound to '{}'", var_name, c)),
+            None => {}
+        }
+
+        self.access_unified(&var_name).attribute = Some(attribute);
+
+        return Ok(());
+    }
+
+    pub fn getattr(&mut self) -> Result<(), Err> {
+        let var_name = match self.pop()? {
+            StackItem::Variable(var_name) => var_name,
+            item => return Err::err_res(format!("Cannot get an attribute of a non-variable: {}", item))
+        };
+
+        for class_var in self.alias_class(&var_name) {
+            match self.unified.get(&class_var).and_then(|unification| unification.attribute.clone()) {
+                Some(attribute) => return self.push(attribute),
+                None => {}
+            }
+        }
+
+        return Err::err_res(format!("{} has no attribute", var_name));
+    }
+
+    // Replaces a bound variable by its value. Unbound variables are replaced by a canonical representative
+    // of the variables they're unified with, so that aliased variables are indistinguishable.
+    fn deref(&self, item: &StackItem) -> Result<StackItem, Err> {
+        match self.item_value(item)? {
+            Some(c) => return Ok(StackItem::Value(c)),
+            None => {
+                match item {
+                    StackItem::Variable(var_name) => {
+                        let representative = self.alias_class(var_name).into_iter().min().unwrap_or(var_name.clone());
+                        return Ok(StackItem::Variable(representative));
+                    }
+
+                    StackItem::Value(_) => return Ok(item.clone())
+                }
+            }
+        }
+    }
+
+    // Compares two items in the standard order of terms: variables < integers < atoms < strings < functors.
+    // Functors are ordered by arity, then name, then arguments from left to right.
+    pub fn compare_items(&self, a: &StackItem, b: &StackItem) -> Result<Ordering, Err> {
+        match (self.deref(a)?, self.deref(b)?) {
+            (StackItem::Variable(v1), StackItem::Variable(v2)) => return Ok(v1.cmp(&v2)),
+            (StackItem::Variable(_), StackItem::Value(_)) => return Ok(Ordering::Less),
+            (StackItem::Value(_), StackItem::Variable(_)) => return Ok(Ordering::Greater),
+            (StackItem::Value(c1), StackItem::Value(c2)) => {
+                match (&c1, &c2) {
+                    (Value::StringValue(s1), Value::StringValue(s2)) => return Ok(s1.cmp(s2)),
+                    (Value::Atom(a1), Value::Atom(a2)) => return Ok(a1.name().cmp(&a2.name())),
+                    (Value::Functor(name1, args1), Value::Functor(name2, args2)) => {
+                        let ord = args1.len().cmp(&args2.len()).then(name1.cmp(name2));
+
+                        if ord != Ordering::Equal {
+                            return Ok(ord);
+                        }
+
+                        for (arg1, arg2) in args1.iter().zip(args2.iter()) {
+                            let arg_ord = self.compare_items(arg1, arg2)?;
+
+                            if arg_ord != Ordering::Equal {
+                                return Ok(arg_ord);
+                            }
+                        }
+
+                        return Ok(Ordering::Equal);
+                    }
+
+                    _ => {
+                        match (Number::from_value(&c1), Number::from_value(&c2)) {
+                            // Equal numbers of different types are ordered Float < Rational < Int
+                            (Some(n1), Some(n2)) => {
+                                let ord = arith::total_compare(n1.clone(), n2.clone());
+                                return Ok(ord.then(n1.type_rank().cmp(&n2.type_rank())));
+                            }
+
+                            _ => return Ok(c1.standard_rank().cmp(&c2.standard_rank()))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Pushes -1, 0, or 1 depending on whether the top item is before, equal to, or after the one below it
+    pub fn compare(&mut self) -> Result<(), Err> {
+        let a = self.pop()?;
+        let b = self.pop()?;
+
+        let res = match self.compare_items(&a, &b)? {
+            Ordering::Less => -1,
+            Ordering::Equal => 0,
+            Ordering::Greater => 1
+        };
+
+        return self.push(StackItem::Value(Value::IntValue(BigInt::from(res))));
+    }
+
+    pub fn termlt(&mut self) -> Result<(), Err> {
+        let a = self.pop()?;
+        let b = self.pop()?;
+
+        if self.compare_items(&a, &b)? == Ordering::Less {
+            return Ok(());
+        } else {
+            return Err::err_res(format!("{} is not before {} in the standard order", a, b));
+        }
+    }
+
+    pub fn termeq(&mut self) -> Result<(), Err> {
+        let a = self.pop()?;
+        let b = self.pop()?;
+
+        if self.compare_items(&a, &b)? == Ordering::Equal {
+            return Ok(());
+        } else {
+            return Err::err_res(format!("{} is not identical to {}", a, b));
+        }
+    }
+
+    fn is_ground(&self, item: &StackItem) -> Result<bool, Err> {
+        match self.deref(item)? {
+            StackItem::Variable(_) => return Ok(false),
+            StackItem::Value(Value::Functor(_, args)) => {
+                for arg in &args {
+                    if !self.is_ground(arg)? {
+                        return Ok(false);
+                    }
+                }
+
+                return Ok(true);
+            }
+            StackItem::Value(_) => return Ok(true)
+        }
+    }
+
+    // Pops the top item and fails unless its dereferenced value passes the test
+    fn check_type(&mut self, kind: &str, test: &dyn Fn(&StackItem) -> bool) -> Result<(), Err> {
+        let item = self.pop()?;
+
+        if test(&self.deref(&item)?) {
+            return Ok(());
+        } else {
+            return Err::err_res(format!("{} is not {}", item, kind));
+        }
+    }
+
+    pub fn isvar(&mut self) -> Result<(), Err> {
+        return self.check_type("an unbound variable", &|item| matches!(item, StackItem::Variable(_)));
+    }
+
+    pub fn isint(&mut self) -> Result<(), Err> {
+        return self.check_type("an integer", &|item| matches!(item, StackItem::Value(Value::IntValue(_))));
+    }
+
+    pub fn isfloat(&mut self) -> Result<(), Err> {
+        return self.check_type("a float", &|item| matches!(item, StackItem::Value(Value::FloatValue(_))));
+    }
+
+    pub fn isrational(&mut self) -> Result<(), Err> {
+        return self.check_type("a rational", &|item| matches!(item, StackItem::Value(Value::RationalValue(_))));
+    }
+
+    pub fn isnumber(&mut self) -> Result<(), Err> {
+        return self.check_type("a number", &|item| match item {
+            StackItem::Value(c) => Number::from_value(c).is_some(),
+            StackItem::Variable(_) => false
+        });
+    }
+
+    pub fn isstr(&mut self) -> Result<(), Err> {
+        return self.check_type("a string", &|item| matches!(item, StackItem::Value(Value::StringValue(_))));
+    }
+
+    pub fn isatom(&mut self) -> Result<(), Err> {
+        return self.check_type("an atom", &|item| matches!(item, StackItem::Value(Value::Atom(_))));
+    }
+
+    pub fn isfunctor(&mut self) -> Result<(), Err> {
+        return self.check_type("a functor", &|item| matches!(item, StackItem::Value(Value::Functor(_, _))));
+    }
+
+    pub fn isground(&mut self) -> Result<(), Err> {
+        let item = self.pop()?;
+
+        if self.is_ground(&item)? {
+            return Ok(());
+        } else {
+            return Err::err_res(format!("{} is not ground", item));
+        }
+    }
+
+    // Like Prolog's functor/3: relates a term to its name and arity, which are the next two items on the stack.
+    // If the term is unbound, it's bound to a functor with fresh variables as arguments.
+    fn functor_term(&mut self, term: StackItem) -> Result<(), Err> {
+        let name = self.pop()?;
+        let arity = self.pop()?;
+
+        match self.deref(&term)? {
+            StackItem::Value(Value::Functor(term_name, args)) => {
+                self.unify_items(name, StackItem::Value(Value::StringValue(term_name)))?;
+                return self.unify_items(arity, StackItem::Value(Value::IntValue(BigInt::from(args.len()))));
+            }
+
+            StackItem::Value(c) => {
+                self.unify_items(name, StackItem::Value(c))?;
+                return self.unify_items(arity, StackItem::Value(Value::IntValue(BigInt::from(0))));
+            }
+
+            StackItem::Variable(_) => {
+                self.push(arity)?;
+                let num = self.popidx()?;
+
+                match self.deref(&name)? {
+                    StackItem::Value(Value::StringValue(s)) => {
+                        let args = (0..num).map(|_| self.fresh_var()).collect();
+                        return self.unify_items(term, StackItem::Value(Value::Functor(s, args)));
+                    }
+
+                    StackItem::Value(c) if num == 0 => return self.unify_items(term, StackItem::Value(c)),
+
+                    item => return Err::err_res(format!("Functor name must be a string. Got: {}", item))
+                }
+            }
+        }
+    }
+
+    // Returns the elements of a list, along with the unbound variable at the end if it's a partial list
+    fn read_list(&self, list: &StackItem) -> Result<(Vec<StackItem>, Option<StackItem>), Err> {
+        let mut items = Vec::new();
+        let mut cur = self.deref(list)?;
+
+        loop {
+            match cur {
+                StackItem::Variable(_) => return Ok((items, Some(cur))),
+                StackItem::Value(Value::Functor(ref name, ref args)) if name == CONS && args.len() == 2 => {
+                    items.push(args[0].clone());
+                    cur = self.deref(&args[1])?;
+                }
+                StackItem::Value(Value::Functor(ref name, ref args)) if name == EMPTY && args.is_empty() => return Ok((items, None)),
+                _ => return Err::err_res(format!("Expected a list, but got: {}", list))
+            }
+        }
+    }
+
+    pub fn arity(&mut self) -> Result<(), Err> {
+        let item = self.pop()?;
+
+        match self.deref(&item)? {
+            StackItem::Value(Value::Functor(_, args)) => return self.push(StackItem::Value(Value::IntValue(BigInt::from(args.len())))),
+            StackItem::Value(_) => return self.push(StackItem::Value(Value::IntValue(BigInt::from(0)))),
+            StackItem::Variable(var_name) => return Err::err_res(format!("Cannot take the arity of unbound variable {}", var_name))
+        }
+    }
+
+    // Relates the top item to a list of its name followed by its arguments (Prolog's =..)
+    pub fn univ(&mut self) -> Result<(), Err> {
+        let term = self.pop()?;
+        let list = self.pop()?;
+
+        match self.deref(&term)? {
+            StackItem::Value(Value::Functor(name, args)) => {
+                let mut items = vec![StackItem::Value(Value::StringValue(name))];
+                items.extend(args);
+                return self.unify_items(list, make_list(items, empty_list()));
+            }
+
+            StackItem::Value(c) => return self.unify_items(list, make_list(vec![StackItem::Value(c)], empty_list())),
+
+            StackItem::Variable(_) => {
+                let mut items = match self.read_list(&list)? {
+                    (items, None) if !items.is_empty() => items,
+                    _ => return Err::err_res(format!("Expected a non-empty list to build a term from, but got: {}", list))
+                };
+
+                let args = items.split_off(1);
+
+                match self.deref(&items[0])? {
+                    StackItem::Value(Value::StringValue(name)) => return self.unify_items(term, StackItem::Value(Value::Functor(name, args))),
+                    StackItem::Value(c) if args.is_empty() => return self.unify_items(term, StackItem::Value(c)),
+                    item => return Err::err_res(format!("Functor name must be a string. Got: {}", item))
+                }
+            }
+        }
+    }
+
+    // Pushes a copy of a functor with one argument replaced
+    pub fn setarg(&mut self) -> Result<(), Err> {
+        let idx = self.popidx()?;
+        let term = self.pop()?;
+        let new_arg = self.pop()?;
+
+        match self.deref(&term)? {
+            StackItem::Value(Value::Functor(name, mut args)) => {
+                if idx < args.len() {
+                    args[idx] = new_arg;
+                    return self.push(StackItem::Value(Value::Functor(name, args)));
+                } else {
+                    return Err::err_res(format!("Functor has {} arguments, but tried to set index {}", args.len(), idx));
+                }
+            }
+
+            item => return Err::err_res(format!("Cannot set an argument of a non-functor: {}", item))
+        }
+    }
+
+    fn copy_item(&mut self, item: &StackItem, mapping: &mut HashMap<String, String>) -> Result<StackItem, Err> {
+        match self.deref(item)? {
+            StackItem::Variable(var_name) => {
+                match mapping.get(&var_name) {
+                    Some(new_name) => return Ok(StackItem::Variable(new_name.clone())),
+                    None => {
+                        let new_var = self.fresh_var();
+
+                        match &new_var {
+                            StackItem::Variable(new_name) => mapping.insert(var_name, new_name.clone()),
+                            StackItem::Value(_) => None
+                        };
+
+                        return Ok(new_var);
+                    }
+                }
+            }
+
+            StackItem::Value(Value::Functor(name, args)) => {
+                let mut new_args = Vec::new();
+
+                for arg in &args {
+                    new_args.push(self.copy_item(arg, mapping)?);
+                }
+
+                return Ok(StackItem::Value(Value::Functor(name, new_args)));
+            }
+
+            value => return Ok(value)
+        }
+    }
+
+    // Replaces variables that were copied with their copies, leaving everything else alone
+    fn rename_item(&self, item: &StackItem, mapping: &HashMap<String, String>) -> Result<StackItem, Err> {
+        match self.deref(item)? {
+            StackItem::Variable(var_name) => return Ok(StackItem::Variable(mapping.get(&var_name).cloned().unwrap_or(var_name))),
+            StackItem::Value(Value::Functor(name, args)) => {
+                let mut new_args = Vec::new();
+
+                for arg in &args {
+                    new_args.push(self.rename_item(arg, mapping)?);
+                }
+
+                return Ok(StackItem::Value(Value::Functor(name, new_args)));
+            }
+            value => return Ok(value)
+        }
+    }
+
+    // Gives each copied variable the same disequalities, domain, and attribute as the original, and copies
+    // any finite domain constraints that only involve copied variables.
+    fn copy_constraints(&mut self, mapping: &HashMap<String, String>) -> Result<(), Err> {
+        // Disequalities with variables that weren't copied, which have to be recorded on both sides
+        let mut outside_disunify = Vec::new();
+
+        for (old_name, new_name) in mapping {
+            let mut unification = Unification::new();
+
+            for var_name in self.alias_class(old_name) {
+                match self.unified.get(&var_name) {
+                    Some(old) => {
+                        for other in &old.var_disunify {
+                            let other_name = match self.deref(&StackItem::Variable(other.clone()))? {
+                                StackItem::Variable(other_name) => other_name,
+                                StackItem::Value(_) => other.clone()
+                            };
+
+                        match mapping.get(&other_name) {
+                            Some(copied) => {
+                                unification.var_disunify.insert(copied.clone());
+                            }
+
+                            None => {
+                                unification.var_disunify.insert(other.clone());
+                                outside_disunify.push((other.clone(), new_name.clone()));
+                            }
+                        }
+                    }
+
+                        unification.value_disunify.extend(old.value_disunify.iter().cloned());
+
+                        if unification.attribute.is_none() {
+                            match &old.attribute {
+                                Some(attribute) => unification.attribute = Some(self.rename_item(attribute, mapping)?),
+                                None => {}
+                            }
+                        }
+                    }
+
+                    None => {}
+                }
+            }
+
+            unification.domain = self.var_domain(old_name);
+
+            self.unified.insert(new_name.clone(), unification);
+        }
+
+        for (other, new_name) in outside_disunify {
+            self.access_unified(&other).var_disunify.insert(new_name);
+        }
+
+        let mut new_constraints = Vec::new();
+
+        for constraint in &self.constraints {
+            let mut all_copied = true;
+            let mut any_copied = false;
+
+            for item in constraint.items() {
+                match self.deref(item)? {
+                    StackItem::Variable(var_name) => {
+                        if mapping.contains_key(&var_name) {
+                            any_copied = true;
+                        } else {
+                            all_copied = false;
+                        }
+                    }
+
+                    StackItem::Value(_) => {}
+                }
+            }
+
+            if any_copied && all_copied {
+                new_constraints.push(constraint.map_items(&|item| self.rename_item(item, mapping).unwrap_or(item.clone())));
+            }
+        }
+
+        for constraint in new_constraints {
+            self.post(constraint)?;
+        }
+
+        return Ok(());
+    }
+
+    // Copies a term, replacing each unbound variable with a fresh one. Variables that occur more than once
+    // are replaced by the same fresh variable each time.
+    pub fn copy_term(&mut self, item: &StackItem) -> Result<StackItem, Err> {
+        let mut mapping = HashMap::new();
+
+        let copy = self.copy_item(item, &mut mapping)?;
+        self.copy_constraints(&mapping)?;
+
+        return Ok(copy);
+    }
+
+    // The size of the biggest value bound to any variable, as counted by term_size
+    pub fn max_bound_term_size(&self, limit: usize) -> Result<usize, Err> {
+        let mut biggest = 0;
+
+        for unification in self.unified.values() {
+            if let Some(c) = &unification.value_unify {
+                biggest = biggest.max(self.term_size(&StackItem::Value(c.clone()), limit)?);
+
+                if biggest > limit {
+                    return Ok(biggest);
+                }
+            }
+        }
+
+        return Ok(biggest);
+    }
+
+    // Counts the nodes in a term, following bindings, but stops counting once the count is over the limit
+    pub fn term_size(&self, item: &StackItem, limit: usize) -> Result<usize, Err> {
+        let mut size = 0;
+        let mut todo = vec![item.clone()];
+
+        while let Some(cur) = todo.pop() {
+            size += 1;
+
+            if size > limit {
+                return Ok(size);
+            }
+
+            match self.item_value(&cur)? {
+                Some(Value::Functor(_, args)) => todo.extend(args),
+                _ => {}
+            }
+        }
+
+        return Ok(size);
+    }
+
+    pub fn copyterm(&mut self) -> Result<(), Err> {
+        let item = self.pop()?;
+        let copy = self.copy_term(&item)?;
+
+        return self.push(copy);
+    }
+
+    // Tries each option in turn: the first is applied right away, and the rest are left as choicepoints
+    // that resume at next_idx after applying that option instead.
+    pub fn choose<T>(&mut self, next_idx: usize, options: Vec<T>, apply: &dyn Fn(&mut Environment, T) -> Result<(), Err>) -> Result<(), Err> {
+        let mut options = options.into_iter();
+
+        let first = match options.next() {
+            Some(first) => first,
+            None => return Err::err_res("No options to choose from".to_string())
+        };
+
+        let mut chain = self.take_choicepoint();
+
+        self.depth += 1;
+
+        for option in options.rev() {
+            let mut alt = self.clone();
+            alt.set_choicepoint(chain);
+
+            if apply(&mut alt, option).and_then(|_| alt.propagate()).is_ok() {
+                chain = Some((next_idx, Box::new(alt)));
+            } else {
+                chain = alt.take_choicepoint();
+            }
+        }
+
+        self.set_choicepoint(chain);
+
+        return apply(self, first);
+    }
+
+    fn item_string(&self, item: &StackItem) -> Result<Option<String>, Err> {
+        match self.item_value(item)? {
+            Some(Value::StringValue(s)) => return Ok(Some(s)),
+            Some(c) => return Err::err_res(format!("Expected a string, but got: {}", c)),
+            None => return Ok(None)
+        }
+    }
+
+    fn pop_string(&mut self) -> Result<String, Err> {
+        let item = self.pop()?;
+
+        return self.item_string(&item)?.ok_or(Err::new(format!("Expected a string, but got unbound variable: {}", item)));
+    }
+
+    // Relates the top two items to their concatenation, which is the third. If only the concatenation
+    // is known, every way of splitting it is tried on backtracking.
+    pub fn concat(&mut self, next_idx: usize) -> Result<(), Err> {
+        let a = self.pop()?;
+        let b = self.pop()?;
+        let c = self.pop()?;
+
+        match (self.item_string(&a)?, self.item_string(&b)?, self.item_string(&c)?) {
+            (Some(s1), Some(s2), _) => return self.unify_items(c, StackItem::Value(Value::StringValue(s1 + &s2))),
+            (Some(s1), None, Some(s)) => {
+                match s.strip_prefix(&s1) {
+                    Some(rest) => return self.unify_items(b, StackItem::Value(Value::StringValue(rest.to_string()))),
+                    None => return Err::err_res(format!("'{}' does not start with '{}'", s, s1))
+                }
+            }
+            (None, Some(s2), Some(s)) => {
+                match s.strip_suffix(&s2) {
+                    Some(rest) => return self.unify_items(a, StackItem::Value(Value::StringValue(rest.to_string()))),
+                    None => return Err::err_res(format!("'{}' does not end with '{}'", s, s2))
+                }
+            }
+            (None, None, Some(s)) => {
+                let mut splits: Vec<usize> = s.char_indices().map(|(idx, _)| idx).collect();
+                splits.push(s.len());
+
+                return self.choose(next_idx, splits, &|env, split| {
+                    env.unify_items(a.clone(), StackItem::Value(Value::StringValue(s[..split].to_string())))?;
+                    return env.unify_items(b.clone(), StackItem::Value(Value::StringValue(s[split..].to_string())));
+                });
+            }
+            _ => return Err::err_res(format!("Cannot concatenate {} and {} into {}: not enough arguments are bound", a, b, c))
+        }
+    }
+
+    pub fn strlen(&mut self) -> Result<(), Err> {
+        let s = self.pop_string()?;
+
+        return self.push(StackItem::Value(Value::IntValue(BigInt::from(s.chars().count()))));
+    }
+
+    // Pushes the substring of the top item starting at the (character) index below it, with the length below that
+    pub fn substr(&mut self) -> Result<(), Err> {
+        let s = self.pop_string()?;
+        let start = self.popidx()?;
+        let len = self.popidx()?;
+
+        if start.checked_add(len).is_none_or(|end| end > s.chars().count()) {
+            return Err::err_res(format!("Substring of length {} starting at {} is out of bounds for '{}'", len, start, s));
+        }
+
+        return self.push(StackItem::Value(Value::StringValue(s.chars().skip(start).take(len).collect())));
+    }
+
+    // Pushes the list of pieces of the top string, split on the separator below it
+    pub fn strsplit(&mut self) -> Result<(), Err> {
+        let s = self.pop_string()?;
+        let sep = self.pop_string()?;
+
+        if sep.is_empty() {
+            return Err::err_res(format!("Cannot split '{}' on the empty string", s));
+        }
+
+        let pieces = s.split(sep.as_str()).map(|piece| StackItem::Value(Value::StringValue(piece.to_string()))).collect();
+
+        return self.push(make_list(pieces, empty_list()));
+    }
+
+    // Pushes the (character) index of an occurrence of the second string in the top string, trying later
+    // occurrences on backtracking
+    pub fn strindex(&mut self, next_idx: usize) -> Result<(), Err> {
+        let s = self.pop_string()?;
+        let sub = self.pop_string()?;
+
+        let indices: Vec<usize> = s.match_indices(sub.as_str()).map(|(idx, _)| s[..idx].chars().count()).collect();
+
+        return self.choose(next_idx, indices, &|env, idx| env.push(StackItem::Value(Value::IntValue(BigInt::from(idx)))));
+    }
+
+    // Relates a string to the list of its character codes
+    pub fn chars(&mut self) -> Result<(), Err> {
+        let s_item = self.pop()?;
+        let list = self.pop()?;
+
+        match self.item_string(&s_item)? {
+            Some(s) => {
+                let codes = s.chars().map(|c| StackItem::Value(Value::IntValue(BigInt::from(c as u32)))).collect();
+                return self.unify_items(list, make_list(codes, empty_list()));
+            }
+
+            None => {
+                let codes = match self.read_list(&list)? {
+                    (codes, None) => codes,
+                    _ => return Err::err_res(format!("Cannot build a string from partial list {}", list))
+                };
+
+                let mut s = String::new();
+
+                for code in &codes {
+                    let c = match self.item_value(code)? {
+                        Some(Value::IntValue(i)) => i.to_u32().and_then(std::char::from_u32),
+                        _ => None
+                    };
+
+                    match c {
+                        Some(c) => s.push(c),
+                        None => return Err::err_res(format!("Not a character code: {}", code))
+                    }
+                }
+
+                return self.unify_items(s_item, StackItem::Value(Value::StringValue(s)));
+            }
+        }
+    }
+
+    pub fn tostr(&mut self) -> Result<(), Err> {
+        let item = self.pop()?;
+
+        let s = match self.resolve_item(&item)? {
+            StackItem::Value(Value::Atom(atom)) => atom.name(),
+            resolved => resolved.to_string()
+        };
+
+        return self.push(StackItem::Value(Value::StringValue(s)));
+    }
+
+    pub fn parseint(&mut self) -> Result<(), Err> {
+        let s = self.pop_string()?;
+
+        match BigInt::parse_bytes(s.trim().as_bytes(), 10) {
+            Some(i) => return self.push(StackItem::Value(Value::IntValue(i))),
+            None => return Err::err_res(format!("Could not parse '{}' as an integer", s))
+        }
+    }
+
+    // Replaces each anonymous variable (_) in a literal term with a fresh variable
+    fn instantiate_literal(&mut self, item: &StackItem) -> StackItem {
+        match item {
+            StackItem::Variable(var_name) if var_name == "_" => return self.fresh_var(),
+            StackItem::Value(Value::Functor(name, args)) => {
+                let new_args = args.iter().map(|arg| self.instantiate_literal(arg)).collect();
+                return StackItem::Value(Value::Functor(name.clone(), new_args));
+            }
+            _ => return item.clone()
+        }
+    }
+
+    pub fn push_literal(&mut self, item: &StackItem) -> Result<(), Err> {
+        let new_item = self.instantiate_literal(item);
+
+        return self.push(new_item);
+    }
+
+    fn pop_list(&mut self) -> Result<(StackItem, Vec<StackItem>, Option<StackItem>), Err> {
+        let list = self.pop()?;
+        let (items, tail) = self.read_list(&list)?;
+
+        return Ok((list, items, tail));
+    }
+
+    // Relates the top item to its length, which is the item below it. If the list is partial and the length
+    // is unknown, longer and longer lists are tried on backtracking.
+    pub fn length(&mut self, retry_idx: usize) -> Result<(), Err> {
+        let (list, items, tail) = self.pop_list()?;
+        let len = self.pop()?;
+
+        let tail = match tail {
+            Some(tail) => tail,
+            None => return self.unify_items(len, StackItem::Value(Value::IntValue(BigInt::from(items.len()))))
+        };
+
+        match self.item_value(&len)? {
+            Some(Value::IntValue(n)) => {
+                match n.to_usize() {
+                    Some(n) if n >= items.len() => {
+                        let new_items = (items.len()..n).map(|_| self.fresh_var()).collect();
+                        return self.unify_items(tail, make_list(new_items, empty_list()));
+                    }
+
+                    _ => return Err::err_res(format!("{} cannot have length {}", list, n))
+                }
+            }
+
+            Some(c) => return Err::err_res(format!("Length must be an integer. Got: {}", c)),
+
+            None => {
+                let head = self.fresh_var();
+                let rest = self.fresh_var();
+
+                self.depth += 1;
+                let mut alt = self.clone();
+
+                if alt.unify_items(tail.clone(), make_list(vec![head], rest)).is_ok() {
+                    alt.push(len.clone())?;
+                    alt.push(list)?;
+                    self.set_choicepoint(Some((retry_idx, Box::new(alt))));
+                }
+
+                self.unify_items(tail, empty_list())?;
+                return self.unify_items(len, StackItem::Value(Value::IntValue(BigInt::from(items.len()))));
+            }
+        }
+    }
+
+    // Relates the top two lists to their concatenation, which is the third item. If only the concatenation
+    // is known, every way of splitting it is tried on backtracking.
+    pub fn append(&mut self, next_idx: usize) -> Result<(), Err> {
+        let (a, a_items, a_tail) = self.pop_list()?;
+        let b = self.pop()?;
+        let c = self.pop()?;
+
+        if a_tail.is_none() {
+            return self.unify_items(c, make_list(a_items, b));
+        }
+
+        match self.read_list(&c)? {
+            (c_items, None) => {
+                return self.choose(next_idx, (0..=c_items.len()).collect(), &|env, split| {
+                    env.unify_items(a.clone(), make_list(c_items[..split].to_vec(), empty_list()))?;
+                    return env.unify_items(b.clone(), make_list(c_items[split..].to_vec(), empty_list()));
+                });
+            }
+
+            _ => return Err::err_res(format!("Cannot append {} and {} into {}: not enough arguments are bound", a, b, c))
+        }
+    }
+
+    // Relates an index (on top), a list, and the element of the list at that index (starting from 0).
+    // If the index is unknown, every element is tried on backtracking.
+    pub fn nth(&mut self, next_idx: usize) -> Result<(), Err> {
+        let idx = self.pop()?;
+        let (list, items, _) = self.pop_list()?;
+        let elem = self.pop()?;
+
+        match self.item_value(&idx)? {
+            Some(Value::IntValue(n)) => {
+                match n.to_usize() {
+                    Some(n) if n < items.len() => return self.unify_items(elem, items[n].clone()),
+                    _ => return Err::err_res(format!("Index {} is out of bounds for {}", n, list))
+                }
+            }
+
+            Some(c) => return Err::err_res(format!("Index must be an integer. Got: {}", c)),
+
+            None => {
+                return self.choose(next_idx, (0..items.len()).collect(), &|env, n| {
+                    env.unify_items(idx.clone(), StackItem::Value(Value::IntValue(BigInt::from(n))))?;
+                    return env.unify_items(elem.clone(), items[n].clone());
+                });
+            }
+        }
+    }
+
+    pub fn reverse(&mut self) -> Result<(), Err> {
+        let (a, a_items, a_tail) = self.pop_list()?;
+        let b = self.pop()?;
+
+        if a_tail.is_none() {
+            return self.unify_items(b, make_list(a_items.into_iter().rev().collect(), empty_list()));
+        }
+
+        match self.read_list(&b)? {
+            (b_items, None) => return self.unify_items(a, make_list(b_items.into_iter().rev().collect(), empty_list())),
+            _ => return Err::err_res(format!("Cannot reverse {} into {}: neither is a complete list", a, b))
+        }
+    }
 }
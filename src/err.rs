@@ -1,12 +1,31 @@
-#[derive(Debug)]
+#![allow(clippy::redundant_field_names)]
+
+// Most errors just mean the current branch failed, so we backtrack. Resource errors mean a limit was hit, so
+// they stop the whole program instead, unless a handler set by catch runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrKind {
+    Failure,
+    Resource
+}
+
+#[derive(Debug, Clone)]
 pub struct Err {
-    msg: String
+    msg: String,
+    kind: ErrKind
 }
 
 impl Err {
     pub fn new(msg: String) -> Err {
         Err {
-            msg: msg
+            msg: msg,
+            kind: ErrKind::Failure
+        }
+    }
+
+    pub fn resource(msg: String) -> Err {
+        Err {
+            msg: msg,
+            kind: ErrKind::Resource
         }
     }
 
@@ -17,4 +36,8 @@ impl Err {
     pub fn msg_clone(&self) -> String {
         self.msg.clone()
     }
+
+    pub fn is_resource(&self) -> bool {
+        self.kind == ErrKind::Resource
+    }
 }
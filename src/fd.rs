@@ -0,0 +1,200 @@
+#![allow(clippy::needless_return, clippy::redundant_field_names)]
+
+use std::collections::BTreeSet;
+
+use num_bigint::BigInt;
+
+use crate::stackitem::StackItem;
+
+// A finite domain for a constraint variable. Intervals may be unbounded on either side (e.g., after
+// propagating `X #< 10` with no other information about X), in which case the missing bound is None.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Domain {
+    Interval { lo: Option<BigInt>, hi: Option<BigInt>, holes: BTreeSet<BigInt> },
+    Set(BTreeSet<BigInt>)
+}
+
+impl Domain {
+    pub fn interval(lo: Option<BigInt>, hi: Option<BigInt>) -> Domain {
+        Domain::Interval {
+            lo: lo,
+            hi: hi,
+            holes: BTreeSet::new()
+        }
+    }
+
+    pub fn singleton(i: BigInt) -> Domain {
+        let mut values = BTreeSet::new();
+        values.insert(i);
+        return Domain::Set(values);
+    }
+
+    pub fn contains(&self, i: &BigInt) -> bool {
+        match self {
+            Domain::Interval { lo, hi, holes } => {
+                let above = lo.as_ref().is_none_or(|lo| i >= lo);
+                let below = hi.as_ref().is_none_or(|hi| i <= hi);
+                return above && below && !holes.contains(i);
+            }
+
+            Domain::Set(values) => return values.contains(i)
+        }
+    }
+
+    pub fn min(&self) -> Option<BigInt> {
+        match self {
+            Domain::Interval { lo, hi, holes } => {
+                let mut cur = lo.clone()?;
+
+                while holes.contains(&cur) {
+                    cur += 1;
+                }
+
+                match hi {
+                    Some(hi) if &cur > hi => return None,
+                    _ => return Some(cur)
+                }
+            }
+
+            Domain::Set(values) => return values.iter().next().cloned()
+        }
+    }
+
+    pub fn max(&self) -> Option<BigInt> {
+        match self {
+            Domain::Interval { lo, hi, holes } => {
+                let mut cur = hi.clone()?;
+
+                while holes.contains(&cur) {
+                    cur -= 1;
+                }
+
+                match lo {
+                    Some(lo) if &cur < lo => return None,
+                    _ => return Some(cur)
+                }
+            }
+
+            Domain::Set(values) => return values.iter().next_back().cloned()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Domain::Interval { lo: Some(_), .. } => return self.min().is_none(),
+            Domain::Interval { hi: Some(_), .. } => return self.max().is_none(),
+            Domain::Interval { .. } => return false,
+            Domain::Set(values) => return values.is_empty()
+        }
+    }
+
+    // If the domain contains exactly one value, returns it.
+    pub fn single_value(&self) -> Option<BigInt> {
+        let min = self.min()?;
+
+        if self.max()? == min {
+            return Some(min);
+        } else {
+            return None;
+        }
+    }
+
+    pub fn remove(&self, i: &BigInt) -> Domain {
+        match self {
+            Domain::Interval { lo, hi, holes } => {
+                let mut new_holes = holes.clone();
+
+                if self.contains(i) {
+                    new_holes.insert(i.clone());
+                }
+
+                return Domain::Interval {
+                    lo: lo.clone(),
+                    hi: hi.clone(),
+                    holes: new_holes
+                };
+            }
+
+            Domain::Set(values) => {
+                let mut new_values = values.clone();
+                new_values.remove(i);
+                return Domain::Set(new_values);
+            }
+        }
+    }
+
+    pub fn intersect(&self, other: &Domain) -> Domain {
+        match (self, other) {
+            (Domain::Interval { lo: lo1, hi: hi1, holes: holes1 }, Domain::Interval { lo: lo2, hi: hi2, holes: holes2 }) => {
+                let lo = match (lo1, lo2) {
+                    (Some(a), Some(b)) => Some(a.max(b).clone()),
+                    (a, b) => a.clone().or_else(|| b.clone())
+                };
+
+                let hi = match (hi1, hi2) {
+                    (Some(a), Some(b)) => Some(a.min(b).clone()),
+                    (a, b) => a.clone().or_else(|| b.clone())
+                };
+
+                return Domain::Interval {
+                    lo: lo,
+                    hi: hi,
+                    holes: holes1.union(holes2).cloned().collect()
+                };
+            }
+
+            (Domain::Set(values), other) | (other, Domain::Set(values)) => {
+                return Domain::Set(values.iter().filter(|i| other.contains(i)).cloned().collect());
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Domain {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Domain::Interval { lo, hi, holes } => {
+                let lo_str = lo.as_ref().map_or("inf".to_string(), |lo| lo.to_string());
+                let hi_str = hi.as_ref().map_or("sup".to_string(), |hi| hi.to_string());
+
+                if holes.is_empty() {
+                    write!(f, "{}..{}", lo_str, hi_str)
+                } else {
+                    let hole_strs: Vec<String> = holes.iter().map(|i| i.to_string()).collect();
+                    write!(f, "{}..{} \\ {{{}}}", lo_str, hi_str, hole_strs.join(", "))
+                }
+            }
+
+            Domain::Set(values) => {
+                let value_strs: Vec<String> = values.iter().map(|i| i.to_string()).collect();
+                write!(f, "{{{}}}", value_strs.join(", "))
+            }
+        }
+    }
+}
+
+// Propagators over finite domain variables. Arguments are either variables or integer values.
+#[derive(Clone, Debug)]
+pub enum Constraint {
+    Eq(StackItem, StackItem),
+    Lt(StackItem, StackItem),
+    AllDifferent(Vec<StackItem>)
+}
+
+impl Constraint {
+    pub fn items(&self) -> Vec<&StackItem> {
+        match self {
+            Constraint::Eq(a, b) => return vec![a, b],
+            Constraint::Lt(a, b) => return vec![a, b],
+            Constraint::AllDifferent(items) => return items.iter().collect()
+        }
+    }
+
+    pub fn map_items(&self, f: &dyn Fn(&StackItem) -> StackItem) -> Constraint {
+        match self {
+            Constraint::Eq(a, b) => return Constraint::Eq(f(a), f(b)),
+            Constraint::Lt(a, b) => return Constraint::Lt(f(a), f(b)),
+            Constraint::AllDifferent(items) => return Constraint::AllDifferent(items.iter().map(f).collect())
+        }
+    }
+}
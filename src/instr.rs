@@ -1,12 +1,23 @@
+#![allow(clippy::needless_return, clippy::single_match, clippy::ptr_arg)]
+
 use std::collections::HashMap;
 
 use num_bigint::BigInt;
+use num_rational::BigRational;
+
+use crate::atom::quote_atom;
+use crate::stackitem::StackItem;
 
 #[derive(Clone, Debug)]
 pub enum Instr {
     Int(BigInt),
+    Rational(BigRational),
+    Float(f64),
     Var(String),
     Str(String),
+    Atom(String),
+    List(StackItem),
+    Term(StackItem),
     Goto,
     Fail,
     Print,
@@ -25,6 +36,12 @@ pub enum Instr {
     Div,
     Mul,
     Pow,
+    Floor,
+    Ceiling,
+    Round,
+    Truncate,
+    ToFloat,
+    ToRational,
     Lt,
     Lte,
     Gt,
@@ -33,7 +50,66 @@ pub enum Instr {
     Over,
     PrintStack,
     PrintUnification,
-    Destroy
+    Destroy,
+    FdIn,
+    FdEq,
+    FdLt,
+    AllDifferent,
+    Label,
+    AttrHook,
+    PutAttr,
+    GetAttr,
+    Compare,
+    TermLt,
+    TermEq,
+    IsVar,
+    IsInt,
+    IsFloat,
+    IsRational,
+    IsNumber,
+    IsStr,
+    IsAtom,
+    IsFunctor,
+    IsGround,
+    Arity,
+    Univ,
+    SetArg,
+    CopyTerm,
+    Resolve,
+    Concat,
+    StrLen,
+    SubStr,
+    StrSplit,
+    StrIndex,
+    Chars,
+    ToStr,
+    ParseInt,
+    Length,
+    Append,
+    Nth,
+    Reverse,
+    ReadLine,
+    ReadInt,
+    ReadTerm,
+    Format,
+    WriteQ,
+    Assert,
+    Retract,
+    Lookup,
+    GSet,
+    GGet,
+    BSet,
+    FindAll,
+    BagOf,
+    SetOf,
+    EndGoal,
+    Not,
+    TableCall,
+    Random,
+    RandInt,
+    Seed,
+    Catch,
+    EndCatch
 }
 
 impl Instr {
@@ -49,6 +125,9 @@ impl Instr {
                 }
             }
 
+            Instr::List(ref mut item) => item.substitute(subs_map),
+            Instr::Term(ref mut item) => item.substitute(subs_map),
+
             _ => {}
         }
     }
@@ -65,8 +144,13 @@ impl std::fmt::Display for Instr {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Instr::Int(i) => write!(f, "int {}", i.to_str_radix(10)),
+            Instr::Rational(r) => write!(f, "rational {}r{}", r.numer(), r.denom()),
+            Instr::Float(x) => write!(f, "float {:?}", x),
             Instr::Var(name) => write!(f, "var {}", name),
             Instr::Str(s) => write!(f, "str \"{}\"", escape_str(s)),
+            Instr::Atom(name) => write!(f, "atom {}", quote_atom(name)),
+            Instr::List(item) => write!(f, "list {}", item.quoted()),
+            Instr::Term(item) => write!(f, "term {}", item.quoted()),
             Instr::Goto => write!(f, "goto"),
             Instr::Fail => write!(f, "fail"),
             Instr::Print => write!(f, "print"),
@@ -85,6 +169,12 @@ impl std::fmt::Display for Instr {
             Instr::Div => write!(f, "div"),
             Instr::Mul => write!(f, "mul"),
             Instr::Pow => write!(f, "pow"),
+            Instr::Floor => write!(f, "floor"),
+            Instr::Ceiling => write!(f, "ceiling"),
+            Instr::Round => write!(f, "round"),
+            Instr::Truncate => write!(f, "truncate"),
+            Instr::ToFloat => write!(f, "tofloat"),
+            Instr::ToRational => write!(f, "torational"),
             Instr::Lt => write!(f, "lt"),
             Instr::Gt => write!(f, "gt"),
             Instr::Lte => write!(f, "lte"),
@@ -93,7 +183,66 @@ impl std::fmt::Display for Instr {
             Instr::Over => write!(f, "over"),
             Instr::PrintStack => write!(f, "printstack"),
             Instr::PrintUnification => write!(f, "printunification"),
-            Instr::Destroy => write!(f, "destroy")
+            Instr::Destroy => write!(f, "destroy"),
+            Instr::FdIn => write!(f, "in"),
+            Instr::FdEq => write!(f, "#="),
+            Instr::FdLt => write!(f, "#<"),
+            Instr::AllDifferent => write!(f, "alldifferent"),
+            Instr::Label => write!(f, "label"),
+            Instr::AttrHook => write!(f, "attrhook"),
+            Instr::PutAttr => write!(f, "putattr"),
+            Instr::GetAttr => write!(f, "getattr"),
+            Instr::Compare => write!(f, "compare"),
+            Instr::TermLt => write!(f, "termlt"),
+            Instr::TermEq => write!(f, "termeq"),
+            Instr::IsVar => write!(f, "isvar"),
+            Instr::IsInt => write!(f, "isint"),
+            Instr::IsFloat => write!(f, "isfloat"),
+            Instr::IsRational => write!(f, "isrational"),
+            Instr::IsNumber => write!(f, "isnumber"),
+            Instr::IsStr => write!(f, "isstr"),
+            Instr::IsAtom => write!(f, "isatom"),
+            Instr::IsFunctor => write!(f, "isfunctor"),
+            Instr::IsGround => write!(f, "isground"),
+            Instr::Arity => write!(f, "arity"),
+            Instr::Univ => write!(f, "univ"),
+            Instr::SetArg => write!(f, "setarg"),
+            Instr::CopyTerm => write!(f, "copyterm"),
+            Instr::Resolve => write!(f, "resolve"),
+            Instr::Concat => write!(f, "concat"),
+            Instr::StrLen => write!(f, "strlen"),
+            Instr::SubStr => write!(f, "substr"),
+            Instr::StrSplit => write!(f, "strsplit"),
+            Instr::StrIndex => write!(f, "strindex"),
+            Instr::Chars => write!(f, "chars"),
+            Instr::ToStr => write!(f, "tostr"),
+            Instr::ParseInt => write!(f, "parseint"),
+            Instr::Length => write!(f, "length"),
+            Instr::Append => write!(f, "append"),
+            Instr::Nth => write!(f, "nth"),
+            Instr::Reverse => write!(f, "reverse"),
+            Instr::ReadLine => write!(f, "readline"),
+            Instr::ReadInt => write!(f, "readint"),
+            Instr::ReadTerm => write!(f, "readterm"),
+            Instr::Format => write!(f, "format"),
+            Instr::WriteQ => write!(f, "writeq"),
+            Instr::Assert => write!(f, "assert"),
+            Instr::Retract => write!(f, "retract"),
+            Instr::Lookup => write!(f, "lookup"),
+            Instr::GSet => write!(f, "gset"),
+            Instr::GGet => write!(f, "gget"),
+            Instr::BSet => write!(f, "bset"),
+            Instr::FindAll => write!(f, "findall"),
+            Instr::BagOf => write!(f, "bagof"),
+            Instr::SetOf => write!(f, "setof"),
+            Instr::EndGoal => write!(f, "endgoal"),
+            Instr::Not => write!(f, "not"),
+            Instr::TableCall => write!(f, "tablecall"),
+            Instr::Random => write!(f, "random"),
+            Instr::RandInt => write!(f, "randint"),
+            Instr::Seed => write!(f, "seed"),
+            Instr::Catch => write!(f, "catch"),
+            Instr::EndCatch => write!(f, "endcatch")
         }
     }
 }
@@ -1,3 +1,5 @@
+#![allow(clippy::needless_return, clippy::single_match)]
+
 use std::collections::HashMap;
 
 use crate::err::Err;
@@ -176,7 +178,7 @@ impl MacroProgram {
                             Some((macro_arg_names, macro_body)) => {
                                 let subs_map = make_subs_map(macro_arg_names.to_vec(), macro_args.clone());
 
-                                for stmt in macro_body.to_vec() {
+                                for stmt in macro_body.iter().cloned() {
                                     let mut new_stmt = stmt;
                                     new_stmt.substitute(&subs_map);
                                     new_result.push(new_stmt);
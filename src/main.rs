@@ -1,121 +1,40 @@
+#![allow(clippy::needless_return, clippy::single_match, clippy::ptr_arg, clippy::redundant_field_names, clippy::manual_strip, clippy::needless_borrow, clippy::comparison_to_empty)]
+
 extern crate clap;
 extern crate num_bigint;
+extern crate num_rational;
 extern crate num_traits;
 
+mod arith;
+mod atom;
+mod database;
 mod err;
 mod enkienv;
+mod fd;
 mod instr;
 mod macrolang;
+mod parallel;
+mod parser;
+mod random;
 mod stackitem;
 mod unification;
+mod vm;
+
+#[cfg(test)]
+mod tests;
 
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, BufWriter};
 
 use clap::{Arg, App};
 
 use num_bigint::BigInt;
+use num_rational::BigRational;
 
-use enkienv::Environment;
-use err::Err;
-use stackitem::{StackItem, Value};
 use instr::Instr;
 use macrolang::{MacroInstr, MacroStmt, MacroProgram};
-
-fn execute(instrs: Vec<Instr>, debug: bool) -> Result<(), Err> {
-    let mut env = Environment::new();
-
-    let mut i = 0;
-
-    loop {
-        let instr = instrs[i].clone();
-
-        i += 1;
-
-        let result = match instr {
-            Instr::Var(var_name) => env.push(StackItem::Variable(var_name)),
-            Instr::Fresh => {
-                let fresh_var_name = format!("T_{}", env.fresh_counter);
-                env.fresh_counter += 1;
-                env.push(StackItem::Variable(fresh_var_name))
-            },
-            Instr::Fail => Err::err_res("fail".to_string()),
-            Instr::Print => env.print(),
-            Instr::Int(i) => env.push(StackItem::Value(Value::IntValue(i))),
-            Instr::Str(s) => env.push(StackItem::Value(Value::StringValue(s))),
-            Instr::Unify   => env.unify(),
-            Instr::Disunify => env.disunify(),
-            Instr::Pop     => env.pop().map(|_x| ()), // Drop the returned item because we don't need it here
-            Instr::Dup     => env.dup(),
-            Instr::Project => env.project(),
-            Instr::NameOf  => env.nameof(),
-            Instr::Functor => env.functor(),
-            Instr::Swap    => env.swap(),
-            Instr::Destroy => env.destroy(),
-            Instr::Add  => env.add(),
-            Instr::Sub => env.sub(),
-            Instr::Mul => env.mul(),
-            Instr::Div => env.div(),
-            Instr::Pow => env.pow(),
-            Instr::Lt => env.lt(),
-            Instr::Gt => env.gt(),
-            Instr::Lte => env.lte(),
-            Instr::Gte => env.gte(),
-            Instr::Rot => env.rot(),
-            Instr::Over => env.over(),
-            Instr::PrintStack => env.print_stack(),
-            Instr::PrintUnification => env.print_unification(),
-            Instr::Goto => {
-                match env.popidx() {
-                    Ok(idx) => {
-                        i = idx;
-                        Ok(()) // TODO: Should it be an error if i >= instrs.len()?
-                    }
-                    Err(err) => Err(err)
-                }
-            },
-            Instr::GotoChoice => { // This adds a choicepoint. If we fail, we'll jump to the location indicated by idx at the top of the stack
-                match env.popidx() {
-                    Ok(idx) => {
-                        env.choicepoint = Some((idx, Box::new(env.clone())));
-                        Ok(())
-                    }
-                    Err(err) => Err(err)
-                }
-            }
-        };
-
-        if result.is_err() {
-            match env.choicepoint {
-                Some((idx, new_env)) => {
-                    env.data = new_env.data;
-                    env.unified = new_env.unified;
-                    env.choicepoint = new_env.choicepoint;
-                    i = idx;
-                },
-                None => return result
-            }
-        }
-
-        if i >= instrs.len() {
-            break;
-        }
-    }
-
-    if debug {
-        println!("");
-        println!("Stack at end of program:");
-        println!("{:?}", env.data);
-        println!();
-
-        println!("Unification state at end of program:");
-        println!("{:?}", env.unified);
-        println!();
-    }
-
-    return Ok(());
-}
+use vm::{VM, DEFAULT_GC_INTERVAL, DepthMeasure, Limits, SearchOptions, SearchStrategy};
 
 fn process_str_const(s: &String) -> Option<String> {
     let temp_str =
@@ -130,10 +49,69 @@ fn process_str_const(s: &String) -> Option<String> {
     return Some((&temp_str[start_pos + 1..end_pos]).to_string());
 }
 
+// Rationals are written the same way as in terms and when printed, e.g., rational 1r3
+fn parse_rational(s: &str) -> Option<BigRational> {
+    let mut parts = s.splitn(2, 'r');
+
+    let numer = BigInt::parse_bytes(parts.next()?.as_bytes(), 10)?;
+    let denom = match parts.next() {
+        Some(denom_str) => BigInt::parse_bytes(denom_str.as_bytes(), 10)?,
+        None => BigInt::from(1)
+    };
+
+    if denom == BigInt::from(0) {
+        return None;
+    }
+
+    return Some(BigRational::new(numer, denom));
+}
+
+// Handles escapes in a single pass, so that an escaped backslash can't start another escape
+fn unescape_quoted(s: &str) -> String {
+    let mut res = String::new();
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('t') => res.push('\t'),
+            Some('r') => res.push('\r'),
+            Some(escaped) => res.push(escaped),
+            None => res.push('\\')
+        }
+    }
+
+    return res;
+}
+
+// Atoms are either written plainly (e.g., atom foo) or in single quotes (e.g., atom 'hello world')
+fn process_atom_const(s: &str) -> Option<String> {
+    if s.starts_with('\'') {
+        let end_pos = s.rfind('\'')?;
+
+        if end_pos == 0 {
+            return None;
+        }
+
+        return Some(unescape_quoted(&s[1..end_pos]));
+    } else {
+        return s.split_whitespace().next().map(|name| name.to_string());
+    }
+}
+
 fn load_instrs(filename: String) -> Option<Vec<Instr>> {
     let file = File::open(filename).unwrap(); // TODO: Handle this better
     let reader = BufReader::new(file);
 
+    return parse_instrs(reader.lines().map(|line| line.unwrap()));
+}
+
+fn parse_instrs(lines: impl Iterator<Item = String>) -> Option<Vec<Instr>> {
     let mut instrs = Vec::new();
 
     let mut locations = HashMap::new();
@@ -142,9 +120,7 @@ fn load_instrs(filename: String) -> Option<Vec<Instr>> {
 
     let mut error = false;
 
-    for line in reader.lines() {
-        let line_str = line.unwrap();
-
+    for line_str in lines {
         match parse_macro_instr(&line_str) {
             Some(MacroInstr::Lit(instr)) => {
                 instrs.push(instr);
@@ -152,7 +128,7 @@ fn load_instrs(filename: String) -> Option<Vec<Instr>> {
 
             Some(MacroInstr::Quote(_split)) => {
                 error = true;
-                println!("Quote not allowed in .envm files!");
+                eprintln!("Quote not allowed in .envm files!");
             }
 
             Some(MacroInstr::Label(label_name)) => {
@@ -180,7 +156,7 @@ fn load_instrs(filename: String) -> Option<Vec<Instr>> {
             }
 
             None => {
-                println!("Unknown label: {}", label_name);
+                eprintln!("Unknown label: {}", label_name);
                 error = true;
             }
         }
@@ -202,6 +178,28 @@ fn parse_macro_instr(line_str: &String) -> Option<MacroInstr> {
     } else if opcode == "int" {
         let big_int = BigInt::parse_bytes(split[1].as_bytes(), 10).unwrap();
         return Some(MacroInstr::Lit(Instr::Int(big_int)));
+    } else if opcode == "rational" {
+        match parse_rational(split[1]) {
+            Some(r) => {
+                return Some(MacroInstr::Lit(Instr::Rational(r)));
+            }
+
+            None => {
+                eprintln!("Could not parse rational constant in: '{}'", line_str);
+                return None;
+            }
+        }
+    } else if opcode == "float" {
+        match split[1].parse::<f64>() {
+            Ok(x) => {
+                return Some(MacroInstr::Lit(Instr::Float(x)));
+            }
+
+            Err(_) => {
+                eprintln!("Could not parse float constant in: '{}'", line_str);
+                return None;
+            }
+        }
     } else if opcode == "str" {
         let str_const_opt = process_str_const(&(line_str["str".len() + 1..]).to_string());
 
@@ -211,7 +209,42 @@ fn parse_macro_instr(line_str: &String) -> Option<MacroInstr> {
             }
 
             None => {
-                println!("Could not parse string constant in: '{}'", line_str);
+                eprintln!("Could not parse string constant in: '{}'", line_str);
+                return None;
+            }
+        }
+    } else if opcode == "atom" {
+        let atom_const_opt = process_atom_const(line_str["atom".len()..].trim());
+
+        match atom_const_opt {
+            Some(atom_const) => {
+                return Some(MacroInstr::Lit(Instr::Atom(atom_const)));
+            }
+
+            None => {
+                eprintln!("Could not parse atom constant in: '{}'", line_str);
+                return None;
+            }
+        }
+    } else if opcode == "list" {
+        match parser::parse_term(&line_str["list".len()..]) {
+            Ok(item) => {
+                return Some(MacroInstr::Lit(Instr::List(item)));
+            }
+
+            Err(err) => {
+                eprintln!("Could not parse list in '{}': {}", line_str, err.msg_clone());
+                return None;
+            }
+        }
+    } else if opcode == "term" {
+        match parser::parse_term(&line_str["term".len()..]) {
+            Ok(item) => {
+                return Some(MacroInstr::Lit(Instr::Term(item)));
+            }
+
+            Err(err) => {
+                eprintln!("Could not parse term in '{}': {}", line_str, err.msg_clone());
                 return None;
             }
         }
@@ -280,22 +313,156 @@ fn parse_macro_instr(line_str: &String) -> Option<MacroInstr> {
         return Some(MacroInstr::Lit(Instr::PrintUnification));
     } else if opcode == "destroy" {
         return Some(MacroInstr::Lit(Instr::Destroy));
+    } else if opcode == "in" {
+        return Some(MacroInstr::Lit(Instr::FdIn));
+    } else if opcode == "#=" {
+        return Some(MacroInstr::Lit(Instr::FdEq));
+    } else if opcode == "#<" {
+        return Some(MacroInstr::Lit(Instr::FdLt));
+    } else if opcode == "alldifferent" {
+        return Some(MacroInstr::Lit(Instr::AllDifferent));
+    } else if opcode == "label" {
+        return Some(MacroInstr::Lit(Instr::Label));
+    } else if opcode == "attrhook" {
+        return Some(MacroInstr::Lit(Instr::AttrHook));
+    } else if opcode == "putattr" {
+        return Some(MacroInstr::Lit(Instr::PutAttr));
+    } else if opcode == "getattr" {
+        return Some(MacroInstr::Lit(Instr::GetAttr));
+    } else if opcode == "compare" {
+        return Some(MacroInstr::Lit(Instr::Compare));
+    } else if opcode == "termlt" {
+        return Some(MacroInstr::Lit(Instr::TermLt));
+    } else if opcode == "termeq" {
+        return Some(MacroInstr::Lit(Instr::TermEq));
+    } else if opcode == "isvar" {
+        return Some(MacroInstr::Lit(Instr::IsVar));
+    } else if opcode == "isint" {
+        return Some(MacroInstr::Lit(Instr::IsInt));
+    } else if opcode == "isstr" {
+        return Some(MacroInstr::Lit(Instr::IsStr));
+    } else if opcode == "isatom" {
+        return Some(MacroInstr::Lit(Instr::IsAtom));
+    } else if opcode == "isfunctor" {
+        return Some(MacroInstr::Lit(Instr::IsFunctor));
+    } else if opcode == "isground" {
+        return Some(MacroInstr::Lit(Instr::IsGround));
+    } else if opcode == "arity" {
+        return Some(MacroInstr::Lit(Instr::Arity));
+    } else if opcode == "univ" {
+        return Some(MacroInstr::Lit(Instr::Univ));
+    } else if opcode == "setarg" {
+        return Some(MacroInstr::Lit(Instr::SetArg));
+    } else if opcode == "copyterm" {
+        return Some(MacroInstr::Lit(Instr::CopyTerm));
+    } else if opcode == "resolve" {
+        return Some(MacroInstr::Lit(Instr::Resolve));
+    } else if opcode == "concat" {
+        return Some(MacroInstr::Lit(Instr::Concat));
+    } else if opcode == "strlen" {
+        return Some(MacroInstr::Lit(Instr::StrLen));
+    } else if opcode == "substr" {
+        return Some(MacroInstr::Lit(Instr::SubStr));
+    } else if opcode == "strsplit" {
+        return Some(MacroInstr::Lit(Instr::StrSplit));
+    } else if opcode == "strindex" {
+        return Some(MacroInstr::Lit(Instr::StrIndex));
+    } else if opcode == "chars" {
+        return Some(MacroInstr::Lit(Instr::Chars));
+    } else if opcode == "tostr" {
+        return Some(MacroInstr::Lit(Instr::ToStr));
+    } else if opcode == "parseint" {
+        return Some(MacroInstr::Lit(Instr::ParseInt));
+    } else if opcode == "floor" {
+        return Some(MacroInstr::Lit(Instr::Floor));
+    } else if opcode == "ceiling" {
+        return Some(MacroInstr::Lit(Instr::Ceiling));
+    } else if opcode == "round" {
+        return Some(MacroInstr::Lit(Instr::Round));
+    } else if opcode == "truncate" {
+        return Some(MacroInstr::Lit(Instr::Truncate));
+    } else if opcode == "tofloat" {
+        return Some(MacroInstr::Lit(Instr::ToFloat));
+    } else if opcode == "torational" {
+        return Some(MacroInstr::Lit(Instr::ToRational));
+    } else if opcode == "isfloat" {
+        return Some(MacroInstr::Lit(Instr::IsFloat));
+    } else if opcode == "isrational" {
+        return Some(MacroInstr::Lit(Instr::IsRational));
+    } else if opcode == "isnumber" {
+        return Some(MacroInstr::Lit(Instr::IsNumber));
+    } else if opcode == "length" {
+        return Some(MacroInstr::Lit(Instr::Length));
+    } else if opcode == "append" {
+        return Some(MacroInstr::Lit(Instr::Append));
+    } else if opcode == "nth" {
+        return Some(MacroInstr::Lit(Instr::Nth));
+    } else if opcode == "reverse" {
+        return Some(MacroInstr::Lit(Instr::Reverse));
+    } else if opcode == "readline" {
+        return Some(MacroInstr::Lit(Instr::ReadLine));
+    } else if opcode == "readint" {
+        return Some(MacroInstr::Lit(Instr::ReadInt));
+    } else if opcode == "readterm" {
+        return Some(MacroInstr::Lit(Instr::ReadTerm));
+    } else if opcode == "format" {
+        return Some(MacroInstr::Lit(Instr::Format));
+    } else if opcode == "writeq" {
+        return Some(MacroInstr::Lit(Instr::WriteQ));
+    } else if opcode == "assert" {
+        return Some(MacroInstr::Lit(Instr::Assert));
+    } else if opcode == "retract" {
+        return Some(MacroInstr::Lit(Instr::Retract));
+    } else if opcode == "lookup" {
+        return Some(MacroInstr::Lit(Instr::Lookup));
+    } else if opcode == "gset" {
+        return Some(MacroInstr::Lit(Instr::GSet));
+    } else if opcode == "gget" {
+        return Some(MacroInstr::Lit(Instr::GGet));
+    } else if opcode == "bset" {
+        return Some(MacroInstr::Lit(Instr::BSet));
+    } else if opcode == "findall" {
+        return Some(MacroInstr::Lit(Instr::FindAll));
+    } else if opcode == "bagof" {
+        return Some(MacroInstr::Lit(Instr::BagOf));
+    } else if opcode == "setof" {
+        return Some(MacroInstr::Lit(Instr::SetOf));
+    } else if opcode == "endgoal" {
+        return Some(MacroInstr::Lit(Instr::EndGoal));
+    } else if opcode == "not" || opcode == "naf" {
+        return Some(MacroInstr::Lit(Instr::Not));
+    } else if opcode == "tablecall" {
+        return Some(MacroInstr::Lit(Instr::TableCall));
+    } else if opcode == "random" {
+        return Some(MacroInstr::Lit(Instr::Random));
+    } else if opcode == "randint" {
+        return Some(MacroInstr::Lit(Instr::RandInt));
+    } else if opcode == "seed" {
+        return Some(MacroInstr::Lit(Instr::Seed));
+    } else if opcode == "catch" {
+        return Some(MacroInstr::Lit(Instr::Catch));
+    } else if opcode == "endcatch" {
+        return Some(MacroInstr::Lit(Instr::EndCatch));
     } else if opcode == "quote" {
         return Some(MacroInstr::Quote((&split[1..]).iter().map(|x| x.to_string()).collect()));
     } else {
-        println!("Unknown opcode '{}' in: '{}'", opcode, line_str);
+        eprintln!("Unknown opcode '{}' in: '{}'", opcode, line_str);
         return None;
     }
 }
 
 fn load_macro_stmts(filepath: String) -> Option<MacroProgram> {
+    let file = File::open(filepath).unwrap(); // TODO: Handle this better
+    let reader = BufReader::new(file);
+
+    return parse_macro_stmts(reader.lines().map(|line| line.unwrap()));
+}
+
+fn parse_macro_stmts(lines: impl Iterator<Item = String>) -> Option<MacroProgram> {
     let mut stmts = Vec::new();
 
     let mut error = false;
 
-    let file = File::open(filepath).unwrap(); // TODO: Handle this better
-    let reader = BufReader::new(file);
-
     let mut macro_name = "".to_string();
     let mut macro_args = Vec::new();
     let mut macro_stmts = Vec::new();
@@ -305,8 +472,7 @@ fn load_macro_stmts(filepath: String) -> Option<MacroProgram> {
     let mut call_instrs = Vec::new();
     let mut call_name = "".to_string();
 
-    for line in reader.lines() {
-        let line_str = line.unwrap();
+    for line_str in lines {
         let split: Vec<&str> = line_str.split(" ").collect();
         let command = split[0].to_string();
 
@@ -334,7 +500,7 @@ fn load_macro_stmts(filepath: String) -> Option<MacroProgram> {
                 stmts.push(MacroStmt::Macro(temp_name, temp_args, temp_stmts));
             } else {
                 error = true;
-                println!("Unmatched endmacro!");
+                eprintln!("Unmatched endmacro!");
             }
         } else if command.starts_with("$") {
             let name = (&command[1..]).to_string();
@@ -371,7 +537,7 @@ fn load_macro_stmts(filepath: String) -> Option<MacroProgram> {
                 }
             } else {
                 error = true;
-                println!("Unmatched endcall!")
+                eprintln!("Unmatched endcall!")
             }
         } else {
             match parse_macro_instr(&line_str) {
@@ -404,21 +570,21 @@ fn load_macro_stmts(filepath: String) -> Option<MacroProgram> {
 fn run_macro_envm_file(debug: bool, filepath: String) {
     match load_macro_stmts(filepath) {
         None => {
-            println!("Exited due to parsing errors.");
+            eprintln!("Exited due to parsing errors.");
         }
 
         Some(macro_prog) => {
             if debug {
-                println!("Parsed program: ");
-                println!("{:?}", macro_prog);
-                println!();
+                eprintln!("Parsed program: ");
+                eprintln!("{:?}", macro_prog);
+                eprintln!();
             }
 
             let expanded = macro_prog.execute();
 
             if debug {
-                println!("Expanded program:");
-                println!("{:?}", expanded);
+                eprintln!("Expanded program:");
+                eprintln!("{:?}", expanded);
             }
 
             match expanded {
@@ -429,36 +595,153 @@ fn run_macro_envm_file(debug: bool, filepath: String) {
                 }
 
                 Err(err) => {
-                    println!("An error occurred during expansion: {}", err.msg_clone());
+                    eprintln!("An error occurred during expansion: {}", err.msg_clone());
                 }
             }
         }
     }
 }
 
-fn run_envm_file(debug: bool, filepath: String) {
+// Command line options that control how a program is run, as opposed to what it contains
+struct RunOptions {
+    debug: bool,
+    input_path: Option<String>,
+    output_path: Option<String>,
+    flush: bool,
+    search: SearchOptions,
+    jobs: usize,
+    deterministic: bool,
+    limits: Limits,
+    seed: Option<u64>,
+    gc_interval: usize
+}
+
+fn make_vm(instrs: Vec<Instr>, opts: &RunOptions) -> Result<VM, String> {
+    let mut vm = VM::new(instrs);
+
+    match &opts.input_path {
+        Some(path) => {
+            let file = File::open(path).map_err(|err| format!("Could not open input file '{}': {}", path, err))?;
+            vm.set_input(Box::new(BufReader::new(file)));
+        }
+
+        None => {}
+    }
+
+    match &opts.output_path {
+        Some(path) => {
+            let file = File::create(path).map_err(|err| format!("Could not open output file '{}': {}", path, err))?;
+            vm.set_output(Box::new(BufWriter::new(file)));
+        }
+
+        None => {}
+    }
+
+    vm.set_flush(opts.flush);
+    vm.set_search(opts.search.clone());
+    vm.set_jobs(opts.jobs, opts.deterministic);
+    vm.set_limits(opts.limits.clone());
+    vm.set_gc_interval(opts.gc_interval);
+
+    match opts.seed {
+        Some(seed) => vm.set_seed(seed),
+        None => {}
+    }
+
+    return Ok(vm);
+}
+
+fn run_envm_file(opts: &RunOptions, filepath: String) {
     match load_instrs(filepath.to_string()) {
         None => {
-            println!("Exited due to parsing errors.");
+            eprintln!("Exited due to parsing errors.");
         }
 
         Some(instrs) => {
-            if debug {
-                println!("Parsed program:");
-                println!("{:?}", instrs);
-                println!();
+            if opts.debug {
+                eprintln!("Parsed program:");
+                eprintln!("{:?}", instrs);
+                eprintln!();
             }
 
-            match execute(instrs, debug) {
+            let mut vm = match make_vm(instrs, opts) {
+                Ok(vm) => vm,
+                Err(msg) => {
+                    eprintln!("{}", msg);
+                    return;
+                }
+            };
+
+            match vm.run(opts.debug) {
                 Ok(_) => {},
+                Err(err) if err.is_resource() => {
+                    eprintln!("Resource error: {}", err.msg_clone());
+                }
                 Err(err) => {
-                    println!("{}", err.msg_clone());
+                    eprintln!("{}", err.msg_clone());
                 }
             }
         }
     }
 }
 
+fn parse_search_options(matches: &clap::ArgMatches) -> Result<SearchOptions, String> {
+    let mut search = SearchOptions::default();
+
+    match matches.value_of("search") {
+        Some("dfs") | None => {}
+        Some("iddfs") => search.strategy = SearchStrategy::IterativeDeepening,
+        Some("bfs") => search.strategy = SearchStrategy::BreadthFirst,
+        Some(other) => return Err(format!("Unknown search strategy '{}' (expected dfs, iddfs, or bfs)", other))
+    }
+
+    match matches.value_of("depth-measure") {
+        Some("choicepoints") | None => {}
+        Some("calls") => search.measure = DepthMeasure::Calls,
+        Some(other) => return Err(format!("Unknown depth measure '{}' (expected choicepoints or calls)", other))
+    }
+
+    match matches.value_of("max-depth") {
+        Some(s) => search.max_depth = Some(s.parse().map_err(|_| format!("Invalid max depth: {}", s))?),
+        None => {}
+    }
+
+    match matches.value_of("depth-step") {
+        Some(s) => search.depth_step = s.parse().map_err(|_| format!("Invalid depth step: {}", s))?,
+        None => {}
+    }
+
+    return Ok(search);
+}
+
+fn parse_limit(matches: &clap::ArgMatches, name: &str) -> Result<Option<usize>, String> {
+    match matches.value_of(name) {
+        Some(s) => return s.parse().map(Some).map_err(|_| format!("Invalid value for --{}: {}", name, s)),
+        None => return Ok(None)
+    }
+}
+
+fn parse_limits(matches: &clap::ArgMatches) -> Result<Limits, String> {
+    let timeout = match matches.value_of("timeout") {
+        Some(s) => {
+            match s.parse::<f64>() {
+                Ok(secs) if secs >= 0.0 => Some(std::time::Duration::from_secs_f64(secs)),
+                _ => return Err(format!("Invalid value for --timeout: {}", s))
+            }
+        }
+
+        None => None
+    };
+
+    return Ok(Limits {
+        max_steps: parse_limit(matches, "max-steps")?,
+        max_choicepoints: parse_limit(matches, "max-choicepoints")?,
+        max_stack: parse_limit(matches, "max-stack")?,
+        max_term_size: parse_limit(matches, "max-term-size")?,
+        timeout: timeout
+    });
+}
+
 fn main() {
     let matches = App::new("EnkiVM")
         .version("0.1.0")
@@ -467,6 +750,68 @@ fn main() {
         .arg(Arg::with_name("debug")
                 .long("debug")
                 .help("Whether to print out additional debug information before/after execution"))
+        .arg(Arg::with_name("input")
+                .long("input")
+                .takes_value(true)
+                .help("A file to read input from instead of stdin"))
+        .arg(Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .help("A file to write the program's output to instead of stdout"))
+        .arg(Arg::with_name("flush")
+                .long("flush")
+                .help("Flush the program's output after every print instead of only when the program ends or reads input"))
+        .arg(Arg::with_name("search")
+                .long("search")
+                .takes_value(true)
+                .help("The search strategy: dfs (the default), iddfs (iterative deepening), or bfs"))
+        .arg(Arg::with_name("depth-measure")
+                .long("depth-measure")
+                .takes_value(true)
+                .help("What counts towards the depth of a state: choicepoints (the default) or calls"))
+        .arg(Arg::with_name("max-depth")
+                .long("max-depth")
+                .takes_value(true)
+                .help("Fail in states deeper than this. With iddfs, the largest bound to try"))
+        .arg(Arg::with_name("depth-step")
+                .long("depth-step")
+                .takes_value(true)
+                .help("How much iddfs increases the depth bound by on each restart (default 1)"))
+        .arg(Arg::with_name("jobs")
+                .long("jobs")
+                .takes_value(true)
+                .help("How many worker threads explore top-level choicepoints in parallel (default 1). Programs that use the database, gset, tables, random numbers or input run sequentially"))
+        .arg(Arg::with_name("deterministic")
+                .long("deterministic")
+                .help("With --jobs, print output in the same order as running sequentially. Without it, the output is the same, but branches print in the order they finish"))
+        .arg(Arg::with_name("max-steps")
+                .long("max-steps")
+                .takes_value(true)
+                .help("Stop with a resource error after running this many instructions"))
+        .arg(Arg::with_name("max-choicepoints")
+                .long("max-choicepoints")
+                .takes_value(true)
+                .help("Stop with a resource error if more than this many choicepoints are waiting at once"))
+        .arg(Arg::with_name("max-stack")
+                .long("max-stack")
+                .takes_value(true)
+                .help("Stop with a resource error if the stack has more than this many items"))
+        .arg(Arg::with_name("max-term-size")
+                .long("max-term-size")
+                .takes_value(true)
+                .help("Stop with a resource error if any term on the stack or bound to a variable is bigger than this"))
+        .arg(Arg::with_name("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .help("Stop with a resource error after this many seconds"))
+        .arg(Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .help("Seed for random and randint, to get the same numbers on every run (default: based on the time)"))
+        .arg(Arg::with_name("gc-interval")
+                .long("gc-interval")
+                .takes_value(true)
+                .help(&format!("Steps between removing unreachable variables from the unification table, or 0 to never remove them (default {})", DEFAULT_GC_INTERVAL)))
         .arg(Arg::with_name("file")
                 .index(1)
                 .help("The file containing code to execute"))
@@ -474,12 +819,83 @@ fn main() {
 
     let debug = matches.is_present("debug");
 
+    let search = match parse_search_options(&matches) {
+        Ok(search) => search,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            return;
+        }
+    };
+
+    let limits = match parse_limits(&matches) {
+        Ok(limits) => limits,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            return;
+        }
+    };
+
+    let seed = match matches.value_of("seed") {
+        Some(s) => {
+            match s.parse::<u64>() {
+                Ok(seed) => Some(seed),
+                Err(_) => {
+                    eprintln!("Invalid seed: {}", s);
+                    return;
+                }
+            }
+        }
+
+        None => None
+    };
+
+    let gc_interval = match matches.value_of("gc-interval") {
+        Some(s) => {
+            match s.parse::<usize>() {
+                Ok(gc_interval) => gc_interval,
+                Err(_) => {
+                    eprintln!("Invalid GC interval: {}", s);
+                    return;
+                }
+            }
+        }
+
+        None => DEFAULT_GC_INTERVAL
+    };
+
+    let jobs = match matches.value_of("jobs") {
+        Some(s) => {
+            match s.parse::<usize>() {
+                Ok(jobs) if jobs > 0 => jobs,
+                _ => {
+                    eprintln!("Invalid number of jobs: {}", s);
+                    return;
+                }
+            }
+        }
+
+        None => 1
+    };
+
+    let opts = RunOptions {
+        debug: debug,
+        input_path: matches.value_of("input").map(|s| s.to_string()),
+        output_path: matches.value_of("output").map(|s| s.to_string()),
+        flush: matches.is_present("flush"),
+        search: search,
+        jobs: jobs,
+        deterministic: matches.is_present("deterministic"),
+        limits: limits,
+        seed: seed,
+        gc_interval: gc_interval
+    };
+
     match matches.value_of("file") {
         Some(filepath) =>
             if filepath.ends_with(".menvm") {
                 run_macro_envm_file(debug, filepath.to_string());
             } else {
-                run_envm_file(debug, filepath.to_string());
+                run_envm_file(&opts, filepath.to_string());
             }
         None => {}
     }
@@ -0,0 +1,255 @@
+#![allow(clippy::needless_return, clippy::single_match, clippy::redundant_field_names)]
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use crate::err::Err;
+use crate::instr::Instr;
+use crate::vm::{ForkedState, VM};
+
+// A branch of the search that a worker explores on its own. Paths order branches the way depth first search
+// would visit them: when a branch with path P forks, it continues as P + [0], and the alternative becomes P + [1].
+pub struct Task {
+    pub path: Vec<u32>,
+    pub start_idx: usize,
+    pub instrs: Arc<[Instr]>,
+    pub state: ForkedState
+}
+
+pub enum Outcome {
+    Succeeded,
+    Failed(Err),
+    Aborted(Err), // Hit a resource limit, which stops the program just like succeeding does
+    Cancelled
+}
+
+impl Outcome {
+    fn stops_program(&self) -> bool {
+        return matches!(self, Outcome::Succeeded | Outcome::Aborted(_));
+    }
+}
+
+// Output is split into segments at each fork, each labeled with the path of the branch that printed it
+pub type Segment = (Vec<u32>, Vec<u8>);
+
+pub struct TaskResult {
+    pub path: Vec<u32>, // The path of the branch when it finished
+    pub segments: Vec<Segment>,
+    pub outcome: Outcome
+}
+
+struct PoolState {
+    tasks: VecDeque<Task>,
+    running: usize,
+    results: Vec<TaskResult>
+}
+
+pub struct Pool {
+    state: Mutex<PoolState>,
+    wakeup: Condvar,
+
+    // Once a branch succeeds, sequential execution would stop there, so we stop the branches that come after the
+    // first one that succeeded. Branches before it would still have run, so they keep going.
+    cutoff: Mutex<Option<Vec<u32>>>,
+
+    // Limits apply to the whole program, not to each branch. Every step in every worker counts, and so does every
+    // choicepoint waiting in a worker or as a task nobody has picked up yet.
+    steps: AtomicUsize,
+    choicepoints: AtomicUsize
+}
+
+impl Pool {
+    fn new() -> Pool {
+        Pool {
+            state: Mutex::new(PoolState {
+                tasks: VecDeque::new(),
+                running: 0,
+                results: Vec::new()
+            }),
+            wakeup: Condvar::new(),
+            cutoff: Mutex::new(None),
+            steps: AtomicUsize::new(0),
+            choicepoints: AtomicUsize::new(0)
+        }
+    }
+
+    pub fn submit(&self, task: Task) {
+        self.choicepoints.fetch_add(1, Ordering::Relaxed);
+        self.state.lock().unwrap().tasks.push_back(task);
+        self.wakeup.notify_one();
+    }
+
+    // Returns how many steps all workers have run, including this one
+    pub fn count_step(&self) -> usize {
+        return self.steps.fetch_add(1, Ordering::Relaxed) + 1;
+    }
+
+    // A worker had old choicepoints waiting the last time it told us, and has new ones now. Returns how many
+    // are waiting across the whole program.
+    pub fn update_choicepoints(&self, old: usize, new: usize) -> usize {
+        if new >= old {
+            return self.choicepoints.fetch_add(new - old, Ordering::Relaxed) + (new - old);
+        } else {
+            return self.choicepoints.fetch_sub(old - new, Ordering::Relaxed) - (old - new);
+        }
+    }
+
+    pub fn should_stop(&self, path: &[u32]) -> bool {
+        match self.cutoff.lock().unwrap().as_ref() {
+            Some(cutoff) => return path > cutoff.as_slice(),
+            None => return false
+        }
+    }
+
+    // Waits for a task, or returns None once there are no tasks left and none running that could make more
+    fn next_task(&self) -> Option<Task> {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            match state.tasks.pop_front() {
+                Some(task) => {
+                    self.choicepoints.fetch_sub(1, Ordering::Relaxed);
+                    state.running += 1;
+                    return Some(task);
+                }
+
+                None => {
+                    if state.running == 0 {
+                        return None;
+                    }
+
+                    state = self.wakeup.wait(state).unwrap();
+                }
+            }
+        }
+    }
+
+    fn finish(&self, result: TaskResult) {
+        if result.outcome.stops_program() {
+            let mut cutoff = self.cutoff.lock().unwrap();
+
+            if cutoff.as_ref().is_none_or(|cutoff| &result.path < cutoff) {
+                *cutoff = Some(result.path.clone());
+            }
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.results.push(result);
+        state.running -= 1;
+
+        // Wake everyone up: either there's nothing left to do, or someone may be waiting on a new task
+        self.wakeup.notify_all();
+    }
+}
+
+fn work(pool: Arc<Pool>) {
+    while let Some(task) = pool.next_task() {
+        if pool.should_stop(&task.path) {
+            pool.finish(TaskResult {
+                path: task.path,
+                segments: Vec::new(),
+                outcome: Outcome::Cancelled
+            });
+        } else {
+            let result = VM::run_task(pool.clone(), task);
+            pool.finish(result);
+        }
+    }
+}
+
+// Runs the root task and everything it forks on a pool of worker threads, then writes the output of every
+// branch that sequential execution would have reached. With deterministic set, the output comes in the same
+// order as sequential execution; otherwise each branch's output comes in the order the branches finished.
+pub fn run_tasks(jobs: usize, deterministic: bool, root: Task, out: &mut dyn Write) -> Result<(), Err> {
+    let pool = Arc::new(Pool::new());
+    pool.submit(root);
+
+    let workers: Vec<thread::JoinHandle<()>> = (0..jobs.max(1)).map(|_| {
+        let pool = pool.clone();
+        thread::spawn(move || work(pool))
+    }).collect();
+
+    for worker in workers {
+        worker.join().map_err(|_| Err::new("A worker thread panicked".to_string()))?;
+    }
+
+    let results = std::mem::take(&mut pool.state.lock().unwrap().results);
+
+    return merge(results, deterministic, out);
+}
+
+fn merge(results: Vec<TaskResult>, deterministic: bool, out: &mut dyn Write) -> Result<(), Err> {
+    // The branch where sequential execution would have stopped
+    let stopped = results.iter().filter(|res| res.outcome.stops_program()).min_by(|a, b| a.path.cmp(&b.path));
+
+    let stopped_path = stopped.map(|res| res.path.clone());
+
+    let aborted = match stopped.map(|res| &res.outcome) {
+        Some(Outcome::Aborted(err)) => Some(err.clone()),
+        _ => None
+    };
+
+    let mut segments: Vec<&Segment> = Vec::new();
+
+    for result in &results {
+        match result.outcome {
+            Outcome::Cancelled => {}
+            _ => segments.extend(result.segments.iter())
+        }
+    }
+
+    if deterministic {
+        segments.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+
+    // A branch past the cutoff may have written output before it was stopped
+    match &stopped_path {
+        Some(cutoff) => segments.retain(|segment| &segment.0 <= cutoff),
+        None => {}
+    }
+
+    for (_, bytes) in segments {
+        out.write_all(bytes).map_err(|err| Err::new(format!("Could not write output: {}", err)))?;
+    }
+
+    match aborted {
+        Some(err) => return Err(err),
+        None => {}
+    }
+
+    if stopped_path.is_some() {
+        return Ok(());
+    }
+
+    // Like sequential execution, report why the last branch failed
+    let last_failure = results.into_iter()
+        .filter_map(|res| match res.outcome {
+            Outcome::Failed(err) => Some((res.path, err)),
+            _ => None
+        })
+        .max_by(|a, b| a.0.cmp(&b.0));
+
+    match last_failure {
+        Some((_, err)) => return Err(err),
+        None => return Err::err_res("No branch succeeded".to_string())
+    }
+}
+
+// Collects a branch's output so it can be split into segments
+pub struct SharedBuffer(pub Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return Ok(());
+    }
+}
@@ -0,0 +1,248 @@
+#![allow(clippy::needless_return)]
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+
+use crate::atom::Atom;
+use crate::err::Err;
+use crate::stackitem::{StackItem, Value, empty_list, make_list};
+
+// Reads terms written in Prolog-like syntax, e.g., f(1, "a", [X | T]). Variables start with an uppercase
+// letter or an underscore, and atoms start with a lowercase letter or are written in single quotes.
+// An atom followed immediately by parentheses is a functor.
+struct TermParser<'a> {
+    chars: Peekable<Chars<'a>>
+}
+
+impl<'a> TermParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.chars.peek() {
+            if c.is_whitespace() {
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        return self.chars.peek().cloned();
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Err> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.chars.next();
+                return Ok(());
+            }
+            Some(c) => return Err::err_res(format!("Expected '{}' but found '{}'", expected, c)),
+            None => return Err::err_res(format!("Expected '{}' but reached the end of the term", expected))
+        }
+    }
+
+    fn read_while(&mut self, pred: &dyn Fn(char) -> bool) -> String {
+        let mut res = String::new();
+
+        while let Some(c) = self.chars.peek() {
+            if pred(*c) {
+                res.push(*c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+
+        return res;
+    }
+
+    // Reads the body of a quoted string or atom, after the opening quote
+    fn read_quoted(&mut self, quote: char) -> Result<String, Err> {
+        let mut res = String::new();
+
+        loop {
+            match self.chars.next() {
+                Some('\\') => {
+                    match self.chars.next() {
+                        Some('n') => res.push('\n'),
+                        Some('t') => res.push('\t'),
+                        Some('r') => res.push('\r'),
+                        Some(c) => res.push(c),
+                        None => return Err::err_res("Unterminated escape sequence".to_string())
+                    }
+                }
+                Some(c) if c == quote => return Ok(res),
+                Some(c) => res.push(c),
+                None => return Err::err_res(format!("Unterminated quoted constant: {}{}", quote, res))
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<StackItem, Err> {
+        let mut num_str = String::new();
+
+        if self.chars.peek() == Some(&'-') {
+            num_str.push('-');
+            self.chars.next();
+        }
+
+        num_str.push_str(&self.read_while(&|c| c.is_ascii_digit()));
+
+        match self.chars.peek() {
+            // Floats are written like 1.5, 1e100, 1.5e-7 or 2E+3
+            Some('.') | Some('e') | Some('E') => {
+                if self.chars.peek() == Some(&'.') {
+                    self.chars.next();
+                    num_str.push('.');
+                    num_str.push_str(&self.read_while(&|c| c.is_ascii_digit()));
+                }
+
+                match self.chars.peek() {
+                    Some('e') | Some('E') => {
+                        self.chars.next();
+                        num_str.push('e');
+
+                        match self.chars.peek() {
+                            Some('+') | Some('-') => num_str.push(self.chars.next().unwrap()),
+                            _ => {}
+                        }
+
+                        num_str.push_str(&self.read_while(&|c| c.is_ascii_digit()));
+                    }
+
+                    _ => {}
+                }
+
+                return num_str.parse::<f64>()
+                    .map(|x| StackItem::Value(Value::FloatValue(x)))
+                    .map_err(|_| Err::new(format!("Could not parse float: {}", num_str)));
+            }
+
+            // Rationals are written like 1r3
+            Some('r') => {
+                self.chars.next();
+                let denom_str = self.read_while(&|c| c.is_ascii_digit());
+
+                match (BigInt::parse_bytes(num_str.as_bytes(), 10), BigInt::parse_bytes(denom_str.as_bytes(), 10)) {
+                    (Some(numer), Some(denom)) if denom != BigInt::from(0) => {
+                        return Ok(StackItem::Value(Value::RationalValue(BigRational::new(numer, denom))));
+                    }
+                    _ => return Err::err_res(format!("Could not parse rational: {}r{}", num_str, denom_str))
+                }
+            }
+
+            _ => {
+                return BigInt::parse_bytes(num_str.as_bytes(), 10)
+                    .map(|i| StackItem::Value(Value::IntValue(i)))
+                    .ok_or(Err::new(format!("Could not parse integer: {}", num_str)));
+            }
+        }
+    }
+
+    fn parse_list(&mut self) -> Result<StackItem, Err> {
+        self.expect('[')?;
+
+        let mut items = Vec::new();
+
+        if self.peek() == Some(']') {
+            self.chars.next();
+            return Ok(empty_list());
+        }
+
+        loop {
+            items.push(self.parse_term()?);
+
+            match self.peek() {
+                Some(',') => {
+                    self.chars.next();
+                }
+
+                Some('|') => {
+                    self.chars.next();
+                    let tail = self.parse_term()?;
+                    self.expect(']')?;
+                    return Ok(make_list(items, tail));
+                }
+
+                _ => {
+                    self.expect(']')?;
+                    return Ok(make_list(items, empty_list()));
+                }
+            }
+        }
+    }
+
+    fn parse_atom_or_functor(&mut self, name: String) -> Result<StackItem, Err> {
+        // Don't skip whitespace here: "f (1)" is not a functor
+        if self.chars.peek() != Some(&'(') {
+            return Ok(StackItem::Value(Value::Atom(Atom::intern(&name))));
+        }
+
+        self.chars.next();
+
+        let mut args = Vec::new();
+
+        if self.peek() == Some(')') {
+            self.chars.next();
+            return Ok(StackItem::Value(Value::Functor(name, args)));
+        }
+
+        loop {
+            args.push(self.parse_term()?);
+
+            match self.peek() {
+                Some(',') => {
+                    self.chars.next();
+                }
+
+                _ => {
+                    self.expect(')')?;
+                    return Ok(StackItem::Value(Value::Functor(name, args)));
+                }
+            }
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<StackItem, Err> {
+        match self.peek() {
+            Some('[') => return self.parse_list(),
+            Some('"') => {
+                self.chars.next();
+                return Ok(StackItem::Value(Value::StringValue(self.read_quoted('"')?)));
+            }
+            Some('\'') => {
+                self.chars.next();
+                let name = self.read_quoted('\'')?;
+                return self.parse_atom_or_functor(name);
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' => return self.parse_number(),
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let name = self.read_while(&|c| c.is_alphanumeric() || c == '_');
+
+                if c.is_uppercase() || c == '_' {
+                    return Ok(StackItem::Variable(name));
+                } else {
+                    return self.parse_atom_or_functor(name);
+                }
+            }
+            Some(c) => return Err::err_res(format!("Unexpected character '{}' in term", c)),
+            None => return Err::err_res("Expected a term but reached the end of the input".to_string())
+        }
+    }
+}
+
+pub fn parse_term(s: &str) -> Result<StackItem, Err> {
+    let mut parser = TermParser {
+        chars: s.chars().peekable()
+    };
+
+    let term = parser.parse_term()?;
+
+    match parser.peek() {
+        None | Some('#') => return Ok(term), // Allow trailing comments, like other instructions
+        Some(c) => return Err::err_res(format!("Unexpected '{}' after the end of the term {}", c, term))
+    }
+}
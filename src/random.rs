@@ -0,0 +1,51 @@
+#![allow(clippy::needless_return)]
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// SplitMix64: tiny, fast, and good enough for randomized search and test generation (but not cryptography).
+// We implement it ourselves so the same seed gives the same numbers everywhere.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng {
+            state: seed
+        }
+    }
+
+    pub fn from_time() -> Rng {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+        return Rng::new(nanos);
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+        return z ^ (z >> 31);
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_float(&mut self) -> f64 {
+        return (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    }
+
+    // Uniform in [0, n), rejecting values that would make the smaller results more likely
+    pub fn below(&mut self, n: u64) -> u64 {
+        let zone = u64::MAX - (u64::MAX % n);
+
+        loop {
+            let r = self.next_u64();
+
+            if r < zone {
+                return r % n;
+            }
+        }
+    }
+}
@@ -1,31 +1,199 @@
+#![allow(clippy::needless_return, clippy::single_match, clippy::ptr_arg)]
+
 use num_bigint::BigInt;
+use num_rational::BigRational;
 
-#[derive(PartialEq, Clone, Debug)]
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::atom::{Atom, quote_atom};
+
+// The names predate the other kinds of values, and are used everywhere
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, Debug)]
 pub enum Value {
     IntValue(BigInt),
+    RationalValue(BigRational),
+    FloatValue(f64),
     StringValue(String),
+    Atom(Atom),
     Functor(String, Vec<StackItem>)
 }
 
-impl std::fmt::Display for Value {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+// Floats are equal when they're equal in the total order used by compare, so NaN unifies with itself, but
+// -0.0 doesn't unify with 0.0.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::IntValue(a), Value::IntValue(b)) => return a == b,
+            (Value::RationalValue(a), Value::RationalValue(b)) => return a == b,
+            (Value::FloatValue(a), Value::FloatValue(b)) => return a.total_cmp(b) == Ordering::Equal,
+            (Value::StringValue(a), Value::StringValue(b)) => return a == b,
+            (Value::Atom(a), Value::Atom(b)) => return a == b,
+            (Value::Functor(name1, args1), Value::Functor(name2, args2)) => return name1 == name2 && args1 == args2,
+            _ => return false
+        }
+    }
+}
+
+impl Value {
+    // Position of this kind of value in the standard order of terms: variables < numbers < atoms < strings < functors
+    pub fn standard_rank(&self) -> usize {
         match self {
-            Value::IntValue(i) => write!(f, "{}", i),
-            Value::StringValue(s) => write!(f, "{}", s),
-            Value::Functor(name, args) => {
-                let str_args: Vec<String> = args.iter().map(|arg| format!("{}", arg)).collect();
-                write!(f, "{}({})", name, str_args.join(", "))
+            Value::IntValue(_) => 1,
+            Value::RationalValue(_) => 1,
+            Value::FloatValue(_) => 1,
+            Value::Atom(_) => 2,
+            Value::StringValue(_) => 3,
+            Value::Functor(_, _) => 4
+        }
+    }
+}
+
+// How much of a term is quoted when it's formatted
+#[derive(Clone, Copy, PartialEq)]
+enum Quoting {
+    // Nothing is quoted, for format's ~w
+    Plain,
+    // Atoms are quoted where needed, but functor names and strings aren't, for print
+    Atoms,
+    // Functor names and strings are quoted too, so the result can be read back in
+    All
+}
+
+fn format_item(item: &StackItem, quoting: Quoting) -> String {
+    match item {
+        StackItem::Variable(s) => return s.clone(),
+        StackItem::Value(c) => return format_value(c, quoting)
+    }
+}
+
+fn format_value(c: &Value, quoting: Quoting) -> String {
+    match c {
+        Value::IntValue(i) => return i.to_string(),
+        Value::RationalValue(r) => return format!("{}r{}", r.numer(), r.denom()),
+        Value::FloatValue(x) => return format!("{:?}", x),
+        Value::StringValue(s) if quoting == Quoting::All => return format!("\"{}\"", escape_quoted(s)),
+        Value::StringValue(s) => return s.clone(),
+        Value::Atom(atom) if quoting != Quoting::Plain => return atom.to_string(),
+        Value::Atom(atom) => return atom.name(),
+        Value::Functor(name, args) if name == EMPTY && args.is_empty() => return "[]".to_string(),
+        Value::Functor(name, args) if name == CONS && args.len() == 2 => {
+            let mut items = vec![format_item(&args[0], quoting)];
+            let mut tail = &args[1];
+
+            loop {
+                match tail {
+                    StackItem::Value(Value::Functor(name, args)) if name == CONS && args.len() == 2 => {
+                        items.push(format_item(&args[0], quoting));
+                        tail = &args[1];
+                    }
+
+                    StackItem::Value(Value::Functor(name, args)) if name == EMPTY && args.is_empty() => {
+                        return format!("[{}]", items.join(", "));
+                    }
+
+                    _ => return format!("[{} | {}]", items.join(", "), format_item(tail, quoting))
+                }
+            }
+        }
+        Value::Functor(name, args) => {
+            let str_args: Vec<String> = args.iter().map(|arg| format_item(arg, quoting)).collect();
+
+            if quoting == Quoting::All {
+                return format!("{}({})", quote_atom(name), str_args.join(", "));
+            } else {
+                return format!("{}({})", name, str_args.join(", "));
             }
         }
     }
 }
 
+fn escape_quoted(s: &String) -> String {
+    return s.replace("\\", "\\\\")
+            .replace("\n", "\\n")
+            .replace("\r", "\\r")
+            .replace("\t", "\\t")
+            .replace("\"", "\\\"");
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", format_value(self, Quoting::Atoms))
+    }
+}
+
+// Lists are represented as cons(Head, Tail) functors ending with empty()
+pub const CONS: &str = "cons";
+pub const EMPTY: &str = "empty";
+
+pub fn make_list(items: Vec<StackItem>, tail: StackItem) -> StackItem {
+    let mut list = tail;
+
+    for item in items.into_iter().rev() {
+        list = StackItem::Value(Value::Functor(CONS.to_string(), vec![item, list]));
+    }
+
+    return list;
+}
+
+pub fn empty_list() -> StackItem {
+    return StackItem::Value(Value::Functor(EMPTY.to_string(), Vec::new()));
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub enum StackItem {
     Variable(String),
     Value(Value)
 }
 
+impl StackItem {
+    // Like Display, but strings and atoms are quoted where necessary so the result can be read back in
+    pub fn quoted(&self) -> String {
+        return format_item(self, Quoting::All);
+    }
+
+    // Like Display, but nothing is quoted
+    pub fn plain(&self) -> String {
+        return format_item(self, Quoting::Plain);
+    }
+
+    pub fn substitute(&mut self, subs_map: &HashMap<String, String>) {
+        match self {
+            StackItem::Variable(ref mut name) => {
+                match subs_map.get(name) {
+                    Some(new_name) => {
+                        *name = new_name.to_string();
+                    }
+
+                    None => {}
+                }
+            }
+
+            StackItem::Value(Value::Functor(_, ref mut args)) => {
+                for arg in args.iter_mut() {
+                    arg.substitute(subs_map);
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    // Adds the names of all the variables in this item, including those nested inside functors
+    pub fn vars(&self, names: &mut Vec<String>) {
+        match self {
+            StackItem::Variable(name) => names.push(name.clone()),
+            StackItem::Value(Value::Functor(_, args)) => {
+                for arg in args {
+                    arg.vars(names);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
 impl std::fmt::Display for StackItem {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
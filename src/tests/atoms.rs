@@ -0,0 +1,59 @@
+use super::{failure, output};
+
+#[test]
+fn atoms_and_strings_with_the_same_name_differ() {
+    failure("
+        str \"abc\"
+        atom abc
+        unify");
+
+    output("
+        atom abc
+        atom 'abc'
+        unify");
+}
+
+#[test]
+fn print_quotes_atoms_where_needed() {
+    assert_eq!(output("
+        atom abc
+        print"), "abc");
+
+    assert_eq!(output("
+        atom 'Hello world'
+        print"), "'Hello world'");
+
+    assert_eq!(output("
+        term f('A', b, \"c\")
+        print"), "f('A', b, c)");
+}
+
+#[test]
+fn quoted_atoms_are_unescaped_in_one_pass() {
+    // An escaped backslash followed by n is a backslash and an n, not a newline
+    assert_eq!(output("
+        atom 'a\\\\nb'
+        tostr
+        strlen
+        print"), "4");
+
+    assert_eq!(output("
+        atom 'it\\'s'
+        tostr
+        print"), "it's");
+}
+
+#[test]
+fn isatom_rejects_strings_and_functors() {
+    output("
+        atom x
+        isatom");
+
+    failure("
+        str \"x\"
+        isatom");
+
+    failure("
+        term x(1)
+        isatom");
+}
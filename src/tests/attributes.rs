@@ -0,0 +1,94 @@
+use super::{failure, output};
+
+// The hook unifies the attribute with the value the variable was bound to, then returns
+const HOOK: &str = "
+    position main
+    goto
+    :hook
+    unify
+    goto
+    :main
+    position hook
+    attrhook
+";
+
+#[test]
+fn hook_runs_when_an_attributed_variable_is_bound() {
+    let source = format!("{}
+        int 5
+        var X
+        putattr
+        int 5
+        var X
+        unify
+        str \"bound\"
+        print", HOOK);
+
+    assert_eq!(output(&source), "bound");
+}
+
+#[test]
+fn hook_failing_undoes_the_binding() {
+    let source = format!("{}
+        int 5
+        var X
+        putattr
+        int 6
+        var X
+        unify", HOOK);
+
+    failure(&source);
+}
+
+#[test]
+fn hook_sees_the_attribute_and_the_value() {
+    let source = "
+        position main
+        goto
+        :hook
+        print
+        print
+        goto
+        :main
+        position hook
+        attrhook
+        atom even
+        var X
+        putattr
+        int 4
+        var X
+        unify
+        str \"done\"
+        print";
+
+    assert_eq!(output(source), "even4done");
+}
+
+#[test]
+fn getattr_finds_the_attribute_through_aliases() {
+    let source = "
+        term dom(1, 2)
+        var X
+        putattr
+        var X
+        var Y
+        unify
+        var Y
+        getattr
+        print";
+
+    assert_eq!(output(source), "dom(1, 2)");
+}
+
+#[test]
+fn putattr_fails_on_a_bound_variable() {
+    let source = "
+        int 1
+        var X
+        unify
+        atom a
+        var X
+        putattr";
+
+    failure(source);
+}
@@ -0,0 +1,70 @@
+use super::{failure, output};
+
+// Compares two terms, given in term literal syntax, with the first on top of the stack
+fn compare(a: &str, b: &str) -> String {
+    return output(&format!("
+        term {}
+        term {}
+        compare
+        print", b, a));
+}
+
+#[test]
+fn kinds_are_ordered_variables_numbers_atoms_strings_functors() {
+    assert_eq!(compare("X", "1"), "-1");
+    assert_eq!(compare("1", "a"), "-1");
+    assert_eq!(compare("a", "\"a\""), "-1");
+    assert_eq!(compare("\"a\"", "f(a)"), "-1");
+    assert_eq!(compare("f(a)", "X"), "1");
+}
+
+#[test]
+fn numbers_are_compared_by_value_across_types() {
+    assert_eq!(compare("2", "1.5"), "1");
+    assert_eq!(compare("1r2", "0.75"), "-1");
+    assert_eq!(compare("1.0", "1"), "-1");
+    assert_eq!(compare("3", "3"), "0");
+}
+
+#[test]
+fn functors_are_compared_by_arity_then_name_then_arguments() {
+    assert_eq!(compare("z(1)", "a(1, 2)"), "-1");
+    assert_eq!(compare("a(2)", "b(1)"), "-1");
+    assert_eq!(compare("f(1, b)", "f(1, a)"), "1");
+    assert_eq!(compare("f(X, [1, 2])", "f(X, [1, 2])"), "0");
+}
+
+#[test]
+fn bound_variables_compare_as_their_values() {
+    let source = "
+        int 2
+        var X
+        unify
+        int 1
+        var X
+        compare
+        print";
+
+    assert_eq!(output(source), "1");
+}
+
+#[test]
+fn termlt_and_termeq_succeed_or_fail() {
+    output("
+        term b
+        term a
+        termlt
+        term f(X)
+        term f(X)
+        termeq");
+
+    failure("
+        term a
+        term b
+        termlt");
+
+    failure("
+        term f(X)
+        term f(Y)
+        termeq");
+}
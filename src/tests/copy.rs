@@ -0,0 +1,92 @@
+use super::{failure, output};
+
+#[test]
+fn copies_get_fresh_variables_shared_where_the_original_shares_them() {
+    let source = "
+        term f(X, Y, X)
+        copyterm
+        var C
+        unify
+        term f(1, 2, 1)
+        var C
+        unify
+        var X
+        isvar
+        var C
+        print";
+
+    assert_eq!(output(source), "f(1, 2, 1)");
+
+    failure("
+        term f(X, Y, X)
+        copyterm
+        term f(1, 2, 3)
+        unify");
+}
+
+#[test]
+fn bound_variables_are_copied_as_their_values() {
+    let source = "
+        int 5
+        var X
+        unify
+        term g(X, Y)
+        copyterm
+        print";
+
+    assert_eq!(output(source), "g(5, T_0)");
+}
+
+#[test]
+fn copies_keep_disequalities_with_variables_outside_the_copy() {
+    let setup = "
+        var Y
+        var X
+        disunify
+        var X
+        copyterm
+        var C
+        unify";
+
+    // Binding either side first, then the other to the same value, has to fail
+    failure(&format!("{}
+        int 1
+        var C
+        unify
+        int 1
+        var Y
+        unify", setup));
+
+    failure(&format!("{}
+        int 1
+        var Y
+        unify
+        int 1
+        var C
+        unify", setup));
+
+    output(&format!("{}
+        int 1
+        var C
+        unify
+        int 2
+        var Y
+        unify", setup));
+}
+
+#[test]
+fn copies_keep_domains() {
+    let source = "
+        term interval(1, 3)
+        var X
+        in
+        var X
+        copyterm
+        var C
+        unify
+        int 4
+        var C
+        unify";
+
+    failure(source);
+}
@@ -0,0 +1,78 @@
+use super::{failure, output};
+
+#[test]
+fn example_sees_the_clauses_from_when_lookup_started() {
+    assert_eq!(output(include_str!("../../examples/database.envm")), "bob\nliz\n");
+}
+
+#[test]
+fn asserted_clauses_survive_backtracking() {
+    let source = "
+        position later
+        gotochoice
+        term fact(1)
+        assert
+        fail
+        :later
+        term fact(X)
+        lookup
+        var X
+        print";
+
+    assert_eq!(output(source), "1");
+}
+
+#[test]
+fn clauses_get_fresh_variables_for_each_lookup() {
+    let source = "
+        term pair(A, A)
+        assert
+        term pair(1, X)
+        lookup
+        term pair(2, Y)
+        lookup
+        var Y
+        var X
+        str \"~w ~w\"
+        format";
+
+    assert_eq!(output(source), "1 2");
+}
+
+#[test]
+fn retract_removes_the_first_matching_clause() {
+    let source = "
+        term n(1)
+        assert
+        term n(2)
+        assert
+        term n(3)
+        assert
+        term n(X)
+        retract
+        var X
+        print
+        position done
+        gotochoice
+        term n(Y)
+        lookup
+        var Y
+        print
+        fail
+        :done";
+
+    assert_eq!(output(source), "123");
+}
+
+#[test]
+fn lookup_and_retract_fail_without_a_match() {
+    failure("
+        term q(1)
+        lookup");
+
+    failure("
+        term q(1)
+        assert
+        term q(2)
+        retract");
+}
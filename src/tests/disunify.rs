@@ -0,0 +1,59 @@
+use super::{failure, output};
+
+#[test]
+fn a_variable_cannot_be_disunified_from_itself() {
+    failure("
+        var X
+        var X
+        disunify");
+}
+
+#[test]
+fn variables_bound_to_equal_values_cannot_be_disunified() {
+    failure("
+        int 1
+        var X
+        unify
+        int 1
+        var Y
+        unify
+        var Y
+        var X
+        disunify");
+
+    output("
+        int 1
+        var X
+        unify
+        int 2
+        var Y
+        unify
+        var Y
+        var X
+        disunify");
+}
+
+#[test]
+fn binding_one_side_later_is_checked() {
+    failure("
+        var Y
+        var X
+        disunify
+        int 1
+        var X
+        unify
+        int 1
+        var Y
+        unify");
+
+    failure("
+        int 1
+        var X
+        unify
+        var Y
+        var X
+        disunify
+        int 1
+        var Y
+        unify");
+}
@@ -0,0 +1,103 @@
+use super::{failure, output};
+
+#[test]
+fn example_prints_every_solution() {
+    assert_eq!(output(include_str!("../../examples/fd.envm")), "123\n132\n231\n");
+}
+
+#[test]
+fn eq_binds_a_value_in_the_domain() {
+    let source = "
+        term interval(1, 5)
+        var x
+        in
+        int 3
+        var x
+        #=
+        var x
+        print";
+
+    assert_eq!(output(source), "3");
+}
+
+#[test]
+fn eq_fails_outside_the_domain() {
+    let source = "
+        term interval(1, 5)
+        var x
+        in
+        int 7
+        var x
+        #=";
+
+    failure(source);
+}
+
+#[test]
+fn lt_narrows_both_domains() {
+    let source = "
+        term interval(1, 3)
+        dup
+        var x
+        in
+        var y
+        in
+        var y
+        var x
+        #<
+        var y
+        label
+        var x
+        label
+        var x
+        print
+        var y
+        print";
+
+    assert_eq!(output(source), "12");
+}
+
+#[test]
+fn label_tries_each_value_in_order() {
+    let source = "
+        position done
+        gotochoice
+        term set(7, 2, 5)
+        var x
+        in
+        var x
+        label
+        var x
+        print
+        fail
+        :done";
+
+    assert_eq!(output(source), "257");
+}
+
+#[test]
+fn alldifferent_fails_without_enough_values() {
+    let source = "
+        term set(1, 2)
+        dup
+        dup
+        var x
+        in
+        var y
+        in
+        var z
+        in
+        var z
+        var y
+        var x
+        int 3
+        alldifferent
+        var x
+        label
+        var y
+        label
+        var z
+        label";
+
+    failure(source);
+}
@@ -0,0 +1,119 @@
+use super::{failure, output};
+
+// Asserts p(3), p(1), p(3), p(2), then runs the goal p(X) from the label p_x after the given code
+fn with_facts(code: &str) -> String {
+    return format!("
+        term p(3)
+        assert
+        term p(1)
+        assert
+        term p(3)
+        assert
+        term p(2)
+        assert
+        {}
+        position done
+        goto
+        :p_x
+        term p(X)
+        lookup
+        endgoal
+        :done", code);
+}
+
+#[test]
+fn example_ends_after_bagof_fails() {
+    assert_eq!(output(include_str!("../../examples/findall.envm")),
+               "findall: [3, 1, 3, 2]\nsetof: [1, 2, 3]\nbagof of nothing failed\n");
+}
+
+#[test]
+fn findall_collects_every_solution_in_order() {
+    assert_eq!(output(&with_facts("
+        var L
+        term f(X)
+        position p_x
+        findall
+        var L
+        print")), "[f(3), f(1), f(3), f(2)]");
+}
+
+#[test]
+fn findall_of_nothing_is_empty_but_bagof_fails() {
+    let goal = "
+        position done
+        goto
+        :q
+        term q(X)
+        lookup
+        endgoal
+        :done";
+
+    // There are no q clauses, so the goal has no solutions
+    assert_eq!(output(&format!("
+        var L
+        var X
+        position q
+        findall
+        var L
+        print
+        {}", goal)), "[]");
+
+    failure(&format!("
+        var L
+        var X
+        position q
+        bagof
+        {}", goal));
+}
+
+#[test]
+fn setof_sorts_and_removes_duplicates() {
+    assert_eq!(output(&with_facts("
+        var S
+        var X
+        position p_x
+        setof
+        var S
+        print")), "[1, 2, 3]");
+}
+
+#[test]
+fn bindings_made_by_the_goal_are_undone() {
+    assert_eq!(output(&with_facts("
+        var L
+        var X
+        position p_x
+        findall
+        var X
+        isvar
+        str \"unbound\"
+        print")), "unbound");
+}
+
+#[test]
+fn variables_outside_the_template_are_not_grouped() {
+    // In Prolog, bagof would give one answer per value of the free variable Y. Here it's one list of all of them.
+    let source = "
+        term r(1, a)
+        assert
+        term r(2, b)
+        assert
+        term r(3, a)
+        assert
+        var L
+        var X
+        position r
+        bagof
+        var L
+        print
+        position done
+        goto
+        :r
+        term r(X, Y)
+        lookup
+        endgoal
+        :done";
+
+    assert_eq!(output(source), "[1, 2, 3]");
+}
@@ -0,0 +1,79 @@
+use super::{failure, output};
+
+#[test]
+fn example_uses_every_directive() {
+    assert_eq!(output(include_str!("../../examples/format.envm")),
+               "Bob and alice are 42 years old\n\
+                f(Hello world, a\"b, 1r3, [x, Y])\n\
+                f('Hello world', \"a\\\"b\", 1r3, [x, Y])\n\
+                'Bob'");
+}
+
+#[test]
+fn w_is_unquoted_but_print_quotes_atoms() {
+    let source = "
+        atom 'Hi there'
+        dup
+        str \"~w|\"
+        format
+        print";
+
+    assert_eq!(output(source), "Hi there|'Hi there'");
+}
+
+#[test]
+fn only_writeq_quotes_functor_names() {
+    let source = "
+        int 1
+        int 1
+        str \"Point\"
+        functor
+        dup
+        print
+        str \" \"
+        print
+        writeq";
+
+    assert_eq!(output(source), "Point(1) 'Point'(1)");
+}
+
+#[test]
+fn writeq_output_reads_back_in() {
+    let source = "
+        term g('A b', \"c\\nd\", [1r2, -3])
+        writeq";
+
+    assert_eq!(output(source), "g('A b', \"c\\nd\", [1r2, -3])");
+}
+
+#[test]
+fn tilde_and_newline() {
+    assert_eq!(output("
+        str \"100~~~n\"
+        format"), "100~\n");
+}
+
+#[test]
+fn nothing_is_written_unless_the_whole_string_formats() {
+    failure("
+        str \"x\"
+        str \"start ~d\"
+        format");
+
+    let source = "
+        position done
+        gotochoice
+        str \"x\"
+        str \"start ~d\"
+        format
+        :done";
+
+    assert_eq!(output(source), "");
+}
+
+#[test]
+fn missing_arguments_fail() {
+    failure("
+        str \"~w ~w\"
+        format");
+}
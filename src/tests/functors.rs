@@ -0,0 +1,98 @@
+use super::{failure, output};
+
+#[test]
+fn arity_counts_arguments() {
+    assert_eq!(output("
+        term f(a, b, c)
+        arity
+        print"), "3");
+
+    assert_eq!(output("
+        term a
+        arity
+        print"), "0");
+}
+
+#[test]
+fn arity_fails_on_unbound_variables() {
+    failure("
+        var X
+        arity");
+}
+
+#[test]
+fn univ_decomposes_a_term() {
+    let source = "
+        var L
+        term point(1, 2)
+        univ
+        var L
+        writeq";
+
+    assert_eq!(output(source), "[\"point\", 1, 2]");
+}
+
+#[test]
+fn univ_builds_a_term() {
+    let source = "
+        term [\"point\", X, 2]
+        var T
+        univ
+        int 1
+        var X
+        unify
+        var T
+        print";
+
+    assert_eq!(output(source), "point(1, 2)");
+}
+
+#[test]
+fn setarg_copies_with_one_argument_replaced() {
+    let source = "
+        atom z
+        term f(a, b, c)
+        int 1
+        setarg
+        print";
+
+    assert_eq!(output(source), "f(a, z, c)");
+
+    failure("
+        atom z
+        term f(a)
+        int 1
+        setarg");
+}
+
+#[test]
+fn functor_decomposes_a_bound_variable() {
+    let source = "
+        term f(a, b)
+        var T
+        unify
+        var Arity
+        var Name
+        var T
+        functor
+        var Arity
+        var Name
+        str \"~w/~w\"
+        format";
+
+    assert_eq!(output(source), "f/2");
+}
+
+#[test]
+fn functor_builds_a_term_with_fresh_arguments() {
+    let source = "
+        int 2
+        str \"g\"
+        var T
+        functor
+        var T
+        arity
+        print";
+
+    assert_eq!(output(source), "2");
+}
@@ -0,0 +1,100 @@
+use super::{expand, load, run_with};
+
+// Binds X to f(T) for a fresh T, binds T to 5, then makes garbage for 300 iterations, then prints X. Each
+// iteration binds a fresh variable that's never used again.
+const GARBAGE: &str = "
+    term f(_)
+    var X
+    unify
+    term f(5)
+    var X
+    unify
+    int 0
+    :loop
+    fresh
+    int 7
+    unify
+    int 1
+    add
+    dup
+    int 300
+    swap
+    compare
+    position loop
+    position exit
+    sub
+    mul
+    position exit
+    add
+    goto
+    :exit
+    pop
+    var X
+    resolve
+    print
+";
+
+// Runs GARBAGE, returning its output and how many variables are left in the unification table
+fn run_garbage(gc_interval: usize) -> (String, usize) {
+    let (mut vm, output) = load(GARBAGE, "");
+    vm.set_gc_interval(gc_interval);
+
+    if let Err(err) = vm.run(false) {
+        panic!("Program failed with: {}", err.msg_clone());
+    }
+
+    let text = String::from_utf8(output.borrow().clone()).unwrap();
+    return (text, vm.env.unified.len());
+}
+
+#[test]
+fn unreachable_fresh_variables_are_collected() {
+    let (collected_text, collected_size) = run_garbage(50);
+    let (text, size) = run_garbage(0);
+
+    assert_eq!(collected_text, "f(5)");
+    assert_eq!(text, "f(5)");
+    assert!(size >= 300, "Without collection, only {} variables were left", size);
+    assert!(collected_size < 60, "With collection, {} variables were left", collected_size);
+}
+
+#[test]
+fn collecting_every_step_does_not_change_any_example() {
+    let examples = vec![
+        include_str!("../../examples/age.envm").to_string(),
+        include_str!("../../examples/database.envm").to_string(),
+        include_str!("../../examples/fd.envm").to_string(),
+        include_str!("../../examples/findall.envm").to_string(),
+        include_str!("../../examples/format.envm").to_string(),
+        include_str!("../../examples/globals.envm").to_string(),
+        include_str!("../../examples/lists.envm").to_string(),
+        include_str!("../../examples/not.envm").to_string(),
+        include_str!("../../examples/terms.envm").to_string(),
+        expand(include_str!("../../examples/lists.menvm")),
+        expand(include_str!("../../examples/parallel.menvm")),
+        expand(include_str!("../../examples/tabling.menvm"))
+    ];
+
+    for source in &examples {
+        let (expected, expected_result) = run_with(source, "", |vm| vm.set_gc_interval(0));
+        let (text, result) = run_with(source, "", |vm| vm.set_gc_interval(1));
+
+        assert_eq!(text, expected);
+        assert_eq!(result.is_ok(), expected_result.is_ok());
+    }
+}
+
+#[test]
+fn program_variables_named_like_fresh_ones_are_kept() {
+    let source = "
+        var T_0
+        int 5
+        unify
+        int 1
+        pop
+        var T_0
+        print";
+
+    let (text, _) = run_with(source, "", |vm| vm.set_gc_interval(1));
+    assert_eq!(text, "5");
+}
@@ -0,0 +1,85 @@
+use super::{failure, output};
+
+#[test]
+fn example_counts_across_backtracking() {
+    assert_eq!(output(include_str!("../../examples/globals.envm")), "count=3 b=0\n");
+}
+
+#[test]
+fn gset_copies_the_value() {
+    let source = "
+        term f(X)
+        atom g
+        gset
+        int 1
+        var X
+        unify
+        atom g
+        gget
+        print";
+
+    assert_eq!(output(source), "f(T_0)");
+}
+
+#[test]
+fn bset_is_undone_by_backtracking() {
+    let source = "
+        int 1
+        atom b
+        bset
+        position later
+        gotochoice
+        int 2
+        atom b
+        bset
+        fail
+        :later
+        atom b
+        gget
+        print";
+
+    assert_eq!(output(source), "1");
+}
+
+#[test]
+fn a_later_gset_wins_over_a_bset_restored_by_backtracking() {
+    let source = "
+        int 1
+        atom x
+        bset
+        position later
+        gotochoice
+        int 2
+        atom x
+        gset
+        fail
+        :later
+        atom x
+        gget
+        print";
+
+    assert_eq!(output(source), "2");
+}
+
+#[test]
+fn a_later_bset_shadows_gset() {
+    let source = "
+        int 1
+        atom x
+        gset
+        int 2
+        atom x
+        bset
+        atom x
+        gget
+        print";
+
+    assert_eq!(output(source), "2");
+}
+
+#[test]
+fn gget_fails_for_unset_globals() {
+    failure("
+        atom nothing
+        gget");
+}
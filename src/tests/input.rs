@@ -0,0 +1,94 @@
+use super::run;
+
+// Runs a program that should succeed on the given input, returning its output
+fn output_with_input(source: &str, input: &str) -> String {
+    let (text, result) = run(source, input);
+    assert!(result.is_ok(), "Program failed with output:\n{}", text);
+
+    return text;
+}
+
+#[test]
+fn readline_reads_lines_without_their_endings() {
+    let source = "
+        readline
+        readline
+        swap
+        str \"[~w][~w]\"
+        format";
+
+    assert_eq!(output_with_input(source, "first\r\nsecond\n"), "[first][second]");
+}
+
+#[test]
+fn reading_past_the_end_gives_end_of_file() {
+    // The arguments to format are popped from the top, so they're in the opposite order to the reads
+    let source = "
+        readline
+        readint
+        readterm
+        str \"~w ~w ~w\"
+        format";
+
+    assert_eq!(output_with_input(source, "only"), "end_of_file end_of_file only");
+}
+
+#[test]
+fn readint_parses_integers() {
+    let source = "
+        readint
+        readint
+        add
+        print";
+
+    assert_eq!(output_with_input(source, " 40 \n2\n"), "42");
+
+    let (_, result) = run("readint", "forty\n");
+    assert!(result.is_err());
+}
+
+#[test]
+fn readterm_reads_terms() {
+    let source = "
+        readterm
+        writeq";
+
+    assert_eq!(output_with_input(source, "f(a, \"b\", [1, 2])\n"), "f(a, \"b\", [1, 2])");
+}
+
+#[test]
+fn readterm_gives_input_variables_fresh_names() {
+    // The X read in is shared within the term, but isn't the program's X
+    let source = "
+        int 5
+        var X
+        unify
+        readterm
+        var T
+        unify
+        int 1
+        var A
+        unify
+        term f(A, B, C)
+        var T
+        unify
+        var B
+        print";
+
+    assert_eq!(output_with_input(source, "f(X, X, _)\n"), "1");
+}
+
+#[test]
+fn writeq_output_of_floats_reads_back() {
+    let written = output_with_input("
+        term f(1e100, 1.5e-7, -2.5, 3E+2, 1.0)
+        writeq", "");
+
+    assert_eq!(written, "f(1e100, 1.5e-7, -2.5, 300.0, 1.0)");
+
+    let source = "
+        readterm
+        writeq";
+
+    assert_eq!(output_with_input(source, &written), written);
+}
@@ -0,0 +1,230 @@
+use std::time::Duration;
+
+use super::run_with;
+use crate::err::Err;
+use crate::vm::Limits;
+
+// Runs the program with the given limits, returning how it ended
+fn run_limited(source: &str, limits: Limits) -> Result<(), Err> {
+    let (_, result) = run_with(source, "", |vm| vm.set_limits(limits));
+    return result;
+}
+
+fn resource_error(result: Result<(), Err>) -> String {
+    match result {
+        Err(err) if err.is_resource() => return err.msg_clone(),
+        Err(err) => panic!("Expected a resource error, but failed with: {}", err.msg_clone()),
+        Ok(()) => panic!("Expected a resource error, but succeeded")
+    }
+}
+
+const LOOP: &str = "
+    :loop
+    position loop
+    goto
+";
+
+#[test]
+fn step_limit_stops_infinite_loops() {
+    let limits = Limits { max_steps: Some(1000), ..Limits::default() };
+    assert_eq!(resource_error(run_limited(LOOP, limits)), "Exceeded the limit of 1000 steps");
+}
+
+#[test]
+fn timeout_stops_infinite_loops() {
+    let limits = Limits { timeout: Some(Duration::from_millis(10)), ..Limits::default() };
+    assert_eq!(resource_error(run_limited(LOOP, limits)), "Exceeded the time limit");
+}
+
+#[test]
+fn resource_errors_are_not_caught_by_backtracking() {
+    // The choicepoint would succeed, but hitting the limit stops everything
+    let source = "
+        position done
+        gotochoice
+        :loop
+        position loop
+        goto
+        :done";
+
+    let limits = Limits { max_steps: Some(1000), ..Limits::default() };
+    resource_error(run_limited(source, limits));
+}
+
+#[test]
+fn catch_runs_a_handler_with_the_error_message() {
+    // The handler starts with the stack as it was at catch, so it's back under the limit
+    let source = "
+        int 1
+        position handler
+        catch
+        :loop
+        int 2
+        position loop
+        goto
+        :handler
+        print
+        print";
+
+    let (text, result) = run_with(source, "", |vm| vm.set_limits(Limits { max_stack: Some(100), ..Limits::default() }));
+    assert!(result.is_ok());
+    assert_eq!(text, "Exceeded the stack limit of 100 items1");
+}
+
+#[test]
+fn running_out_of_steps_can_only_be_caught_once() {
+    let source = "
+        position handler
+        catch
+        :loop
+        position loop
+        goto
+        :handler
+        print
+        position again
+        catch
+        :loop2
+        position loop2
+        goto
+        :again
+        str \"caught twice\"
+        print";
+
+    let (text, result) = run_with(source, "", |vm| vm.set_limits(Limits { max_steps: Some(1000), ..Limits::default() }));
+    assert_eq!(text, "Exceeded the limit of 1000 steps");
+    assert_eq!(resource_error(result), "Exceeded the limit of 1000 steps");
+}
+
+#[test]
+fn handlers_end_at_endcatch_and_on_backtracking() {
+    let limits = Limits { max_steps: Some(1000), ..Limits::default() };
+
+    let source = "
+        position handler
+        catch
+        endcatch
+        :loop
+        position loop
+        goto
+        :handler";
+
+    resource_error(run_limited(source, limits.clone()));
+
+    let source = "
+        position second
+        gotochoice
+        position handler
+        catch
+        fail
+        :second
+        position second
+        goto
+        :handler";
+
+    resource_error(run_limited(source, limits));
+}
+
+#[test]
+fn choicepoint_limit_counts_waiting_choicepoints() {
+    let source = "
+        :loop
+        position loop
+        gotochoice
+        position loop
+        goto";
+
+    let limits = Limits { max_choicepoints: Some(10), ..Limits::default() };
+    assert_eq!(resource_error(run_limited(source, limits)), "Exceeded the limit of 10 choicepoints");
+
+    // Backtracking into a choicepoint removes it, so a long search that never has many waiting is fine
+    let source = "
+        var N
+        list [a | T]
+        length
+        int 50
+        var N
+        unify";
+
+    assert!(run_limited(source, Limits { max_choicepoints: Some(2), ..Limits::default() }).is_ok());
+}
+
+#[test]
+fn limits_count_every_worker_with_jobs() {
+    let run_parallel = |source: &str, limits: Limits| {
+        let (_, result) = run_with(source, "", |vm| {
+            vm.set_limits(limits);
+            vm.set_jobs(2, false);
+        });
+
+        return result;
+    };
+
+    // Each branch only runs a few steps before forking, so the steps only add up to the limit across workers
+    let source = "
+        :alt
+        position alt
+        gotochoice
+        fail";
+
+    let limits = Limits { max_steps: Some(1000), ..Limits::default() };
+    assert_eq!(resource_error(run_parallel(source, limits)), "Exceeded the limit of 1000 steps");
+
+    // The forked alternatives wait as tasks instead of as choicepoints in any worker
+    let source = "
+        :loop
+        position loop
+        gotochoice
+        position loop
+        goto";
+
+    let limits = Limits { max_choicepoints: Some(10), ..Limits::default() };
+    assert_eq!(resource_error(run_parallel(source, limits)), "Exceeded the limit of 10 choicepoints");
+}
+
+#[test]
+fn stack_limit() {
+    let source = "
+        :loop
+        int 1
+        position loop
+        goto";
+
+    let limits = Limits { max_stack: Some(100), ..Limits::default() };
+    assert_eq!(resource_error(run_limited(source, limits)), "Exceeded the stack limit of 100 items");
+}
+
+#[test]
+fn term_size_limit_counts_terms_built_on_the_stack() {
+    let source = "
+        atom z
+        :loop
+        int 1
+        str \"s\"
+        functor
+        position loop
+        goto";
+
+    let limits = Limits { max_term_size: Some(50), ..Limits::default() };
+    assert_eq!(resource_error(run_limited(source, limits)), "Exceeded the term size limit of 50");
+}
+
+#[test]
+fn term_size_limit_counts_growth_through_bindings() {
+    // X = s(T0), T0 = s(T1), ... while only the newest unbound variable is on the stack
+    let source = "
+        var X
+        :loop
+        fresh
+        dup
+        int 1
+        str \"s\"
+        functor
+        rot
+        rot
+        unify
+        position loop
+        goto";
+
+    let limits = Limits { max_term_size: Some(50), max_steps: Some(100000), ..Limits::default() };
+    assert_eq!(resource_error(run_limited(source, limits)), "Exceeded the term size limit of 50");
+}
@@ -0,0 +1,109 @@
+use super::{failure, output};
+
+#[test]
+fn list_literals_build_cons_cells() {
+    assert_eq!(output("
+        list [1, 2 | T]
+        print"), "[1, 2 | T]");
+
+    output("
+        term cons(1, cons(2, []))
+        list [1, 2]
+        unify");
+}
+
+#[test]
+fn length_of_a_list_and_lists_of_a_length() {
+    assert_eq!(output("
+        var N
+        list [a, b, c]
+        length
+        var N
+        print"), "3");
+
+    assert_eq!(output("
+        int 2
+        var L
+        length
+        var L
+        isfunctor
+        var N
+        var L
+        length
+        var N
+        print"), "2");
+}
+
+#[test]
+fn length_enumerates_partial_lists_on_backtracking() {
+    let source = "
+        var N
+        list [a | T]
+        length
+        var N
+        print
+        int 3
+        var N
+        unify";
+
+    assert_eq!(output(source), "123");
+}
+
+#[test]
+fn append_joins_and_splits() {
+    assert_eq!(output("
+        var C
+        list [3]
+        list [1, 2]
+        append
+        var C
+        print"), "[1, 2, 3]");
+
+    assert_eq!(output("
+        position done
+        gotochoice
+        list [1, 2]
+        var B
+        var A
+        append
+        var B
+        var A
+        str \"~w+~w \"
+        format
+        fail
+        :done"), "[]+[1, 2] [1]+[2] [1, 2]+[] ");
+}
+
+#[test]
+fn nth_indexes_from_zero() {
+    assert_eq!(output("
+        var E
+        list [a, b, c]
+        int 1
+        nth
+        var E
+        print"), "b");
+
+    failure("
+        var E
+        list [a, b, c]
+        int 3
+        nth");
+}
+
+#[test]
+fn reverse_works_in_either_direction() {
+    assert_eq!(output("
+        var R
+        list [1, 2, 3]
+        reverse
+        var R
+        print"), "[3, 2, 1]");
+
+    assert_eq!(output("
+        list [1, 2]
+        var L
+        reverse
+        var L
+        print"), "[2, 1]");
+}
@@ -0,0 +1,98 @@
+#![allow(clippy::needless_return)]
+
+// Each module runs small .envm programs through the VM, feeding them input and capturing their output
+mod atoms;
+mod attributes;
+mod compare;
+mod copy;
+mod database;
+mod disunify;
+mod fd;
+mod findall;
+mod format;
+mod functors;
+mod gc;
+mod globals;
+mod input;
+mod limits;
+mod lists;
+mod not;
+mod numbers;
+mod output;
+mod parallel;
+mod random;
+mod resolve;
+mod search;
+mod strings;
+mod tabling;
+mod terms;
+mod types;
+
+use std::cell::RefCell;
+use std::io::Cursor;
+use std::rc::Rc;
+
+use crate::err::Err;
+use crate::parallel::SharedBuffer;
+use crate::{parse_instrs, parse_macro_stmts};
+use crate::vm::VM;
+
+// Loads the program with the given input, returning the VM and the buffer its output goes to
+pub fn load(source: &str, input: &str) -> (VM, Rc<RefCell<Vec<u8>>>) {
+    // Programs are indented to fit in with the test code around them
+    let lines = source.lines().map(|line| line.trim_start().to_string());
+    let instrs = parse_instrs(lines).expect("Could not parse the program");
+    let output = Rc::new(RefCell::new(Vec::new()));
+
+    let vm = VM::with_io(instrs,
+                         Box::new(Cursor::new(input.as_bytes().to_vec())),
+                         Box::new(SharedBuffer(output.clone())));
+
+    return (vm, output);
+}
+
+// Runs the program on the given input after letting setup configure the VM. Returns everything the program
+// printed, and how it ended.
+pub fn run_with(source: &str, input: &str, setup: impl FnOnce(&mut VM)) -> (String, Result<(), Err>) {
+    let (mut vm, output) = load(source, input);
+    setup(&mut vm);
+
+    let result = vm.run(false);
+    let text = String::from_utf8(output.borrow().clone()).expect("Output was not UTF-8");
+
+    return (text, result);
+}
+
+pub fn run(source: &str, input: &str) -> (String, Result<(), Err>) {
+    return run_with(source, input, |_| {});
+}
+
+// Expands a .menvm program into the .envm program it stands for
+pub fn expand(source: &str) -> String {
+    let lines = source.lines().map(|line| line.trim_start().to_string());
+    let program = parse_macro_stmts(lines).expect("Could not parse the macro program");
+
+    match program.execute() {
+        Ok(instrs) => return instrs.iter().map(|instr| format!("{}\n", instr)).collect(),
+        Err(err) => panic!("Could not expand the macro program: {}", err.msg_clone())
+    }
+}
+
+// Runs a program that should succeed, returning its output
+pub fn output(source: &str) -> String {
+    let (text, result) = run(source, "");
+
+    if let Err(err) = result {
+        panic!("Program failed with: {}\nOutput so far:\n{}", err.msg_clone(), text);
+    }
+
+    return text;
+}
+
+// Runs a program that should fail, returning the error message
+pub fn failure(source: &str) -> String {
+    match run(source, "") {
+        (text, Ok(())) => panic!("Program should have failed, but succeeded with output:\n{}", text),
+        (_, Err(err)) => return err.msg_clone()
+    }
+}
@@ -0,0 +1,71 @@
+use super::{failure, output};
+
+// Asserts p(1) and p(2), then runs not on the goal p(N) after the given code
+fn not_p(n: &str) -> String {
+    return format!("
+        term p(1)
+        assert
+        term p(2)
+        assert
+        position goal
+        not
+        str \"succeeded\"
+        print
+        position done
+        goto
+        :goal
+        term p({})
+        lookup
+        endgoal
+        :done", n);
+}
+
+#[test]
+fn example_binds_nothing() {
+    assert_eq!(output(include_str!("../../examples/not.envm")), "not p(3) succeeded, X = X\n");
+}
+
+#[test]
+fn succeeds_only_if_the_goal_has_no_solutions() {
+    assert_eq!(output(&not_p("3")), "succeeded");
+    failure(&not_p("2"));
+    failure(&not_p("_"));
+}
+
+#[test]
+fn naf_is_another_name_for_not() {
+    let source = "
+        position goal
+        naf
+        str \"ok\"
+        print
+        position done
+        goto
+        :goal
+        fail
+        :done";
+
+    assert_eq!(output(source), "ok");
+}
+
+#[test]
+fn choicepoints_from_before_are_kept() {
+    let source = "
+        position second
+        gotochoice
+        str \"first \"
+        print
+        position goal
+        not
+        fail
+        :second
+        str \"second\"
+        print
+        position done
+        goto
+        :goal
+        fail
+        :done";
+
+    assert_eq!(output(source), "first second");
+}
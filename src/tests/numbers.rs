@@ -0,0 +1,102 @@
+use super::{failure, output};
+
+#[test]
+fn rationals_are_written_the_way_they_are_read() {
+    assert_eq!(output("
+        rational 2r6
+        print"), "1r3");
+
+    output("
+        term 1r3
+        rational 1r3
+        unify");
+}
+
+#[test]
+fn arithmetic_promotes_to_the_widest_type() {
+    assert_eq!(output("
+        rational 1r2
+        rational 1r3
+        add
+        print"), "5r6");
+
+    assert_eq!(output("
+        rational 1r2
+        float 0.25
+        add
+        print"), "0.75");
+
+    assert_eq!(output("
+        int 2
+        rational 1r2
+        mul
+        print"), "1");
+}
+
+#[test]
+fn rounding_and_conversions() {
+    assert_eq!(output("
+        rational 7r2
+        floor
+        print
+        rational -7r2
+        ceiling
+        print
+        float 2.5
+        round
+        print
+        float -2.7
+        truncate
+        print"), "3-33-2");
+
+    assert_eq!(output("
+        float 0.5
+        torational
+        print
+        rational 1r4
+        tofloat
+        print"), "1r20.25");
+}
+
+#[test]
+fn number_type_tests() {
+    output("
+        float 1.5
+        isfloat
+        rational 1r2
+        isrational
+        int 1
+        isnumber
+        float 1.5
+        isnumber");
+
+    failure("
+        int 1
+        isfloat");
+
+    failure("
+        atom one
+        isnumber");
+}
+
+#[test]
+fn nan_unifies_with_itself_but_signed_zeros_differ() {
+    output("
+        float NaN
+        float NaN
+        unify");
+
+    failure("
+        float 0.0
+        float -0.0
+        unify");
+}
+
+#[test]
+fn nan_comes_after_every_other_number() {
+    assert_eq!(output("
+        int 1000
+        float NaN
+        compare
+        print"), "1");
+}
@@ -0,0 +1,66 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use super::{output, run, run_with};
+use crate::parallel::SharedBuffer;
+
+struct BrokenWriter;
+
+impl Write for BrokenWriter {
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        return Err(std::io::Error::other("broken"));
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return Ok(());
+    }
+}
+
+#[test]
+fn everything_printed_goes_to_the_sink() {
+    let text = output("
+        str \"a\"
+        printstack
+        pop
+        str \"b\"
+        print");
+
+    assert!(text.starts_with("[Value(StringValue(\"a\"))]\n"), "Got: {}", text);
+    assert!(text.ends_with("\nb"), "Got: {}", text);
+}
+
+#[test]
+fn output_before_a_failure_is_kept() {
+    let (text, result) = run("
+        str \"partial\"
+        print
+        fail", "");
+
+    assert_eq!(text, "partial");
+    assert!(result.is_err());
+}
+
+#[test]
+fn set_output_replaces_the_sink() {
+    let other = Rc::new(RefCell::new(Vec::new()));
+    let sink = other.clone();
+
+    let (text, result) = run_with("
+        str \"elsewhere\"
+        print", "", move |vm| vm.set_output(Box::new(SharedBuffer(sink))));
+
+    assert!(result.is_ok());
+    assert_eq!(text, "");
+    assert_eq!(String::from_utf8(other.borrow().clone()).unwrap(), "elsewhere");
+}
+
+#[test]
+fn write_errors_fail_the_instruction() {
+    let (_, result) = run_with("
+        str \"lost\"
+        print", "", |vm| vm.set_output(Box::new(BrokenWriter)));
+
+    let err = result.expect_err("print should have failed");
+    assert!(err.msg_clone().starts_with("Could not write output"), "Got: {}", err.msg_clone());
+}
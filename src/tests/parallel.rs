@@ -0,0 +1,51 @@
+use super::{expand, run_with};
+
+// Runs the program sequentially, then with three workers and deterministic output, and checks that both
+// print the same thing. Returns the output.
+fn same_in_parallel(source: &str) -> String {
+    let (sequential, sequential_result) = run_with(source, "", |vm| vm.set_seed(1));
+    let (parallel, parallel_result) = run_with(source, "", |vm| {
+        vm.set_seed(1);
+        vm.set_jobs(3, true);
+    });
+
+    assert_eq!(sequential, parallel);
+    assert_eq!(sequential_result.is_ok(), parallel_result.is_ok());
+
+    return sequential;
+}
+
+#[test]
+fn example_prints_every_pair_of_different_digits() {
+    let source = expand(include_str!("../../examples/parallel.menvm"));
+
+    assert_eq!(same_in_parallel(&source), "12\n13\n21\n23\n31\n32\n");
+}
+
+#[test]
+fn without_deterministic_only_the_order_of_output_changes() {
+    let source = expand(include_str!("../../examples/parallel.menvm"));
+    let (sequential, _) = run_with(&source, "", |_| {});
+    let (parallel, parallel_result) = run_with(&source, "", |vm| vm.set_jobs(3, false));
+
+    let mut lines: Vec<&str> = parallel.lines().collect();
+    lines.sort();
+
+    assert!(parallel_result.is_ok());
+    assert_eq!(lines, sequential.lines().collect::<Vec<&str>>());
+}
+
+#[test]
+fn programs_that_share_state_across_branches_match_sequential_runs() {
+    assert_eq!(same_in_parallel(include_str!("../../examples/globals.envm")), "count=3 b=0\n");
+    assert_eq!(same_in_parallel(include_str!("../../examples/database.envm")), "bob\nliz\n");
+    same_in_parallel(include_str!("../../examples/random.envm"));
+}
+
+#[test]
+fn other_examples_match_sequential_runs() {
+    same_in_parallel(include_str!("../../examples/fd.envm"));
+    same_in_parallel(include_str!("../../examples/findall.envm"));
+    same_in_parallel(include_str!("../../examples/not.envm"));
+    same_in_parallel(&expand(include_str!("../../examples/lists.menvm")));
+}
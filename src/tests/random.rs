@@ -0,0 +1,89 @@
+use super::{failure, output, run_with};
+
+// Prints ten random integers from 1 to 6
+const DICE: &str = "
+    position done
+    gotochoice
+    var N
+    list [a | T]
+    length
+    int 6
+    int 1
+    randint
+    str \"~w \"
+    format
+    int 10
+    var N
+    unify
+    :done";
+
+fn seeded(source: &str, seed: u64) -> String {
+    let (text, result) = run_with(source, "", |vm| vm.set_seed(seed));
+    assert!(result.is_ok(), "Program failed with output:\n{}", text);
+
+    return text;
+}
+
+#[test]
+fn the_same_seed_gives_the_same_numbers() {
+    assert_eq!(seeded(DICE, 42), seeded(DICE, 42));
+    assert_ne!(seeded(DICE, 42), seeded(DICE, 43));
+}
+
+#[test]
+fn randint_stays_within_its_bounds() {
+    for seed in 0..20 {
+        for word in seeded(DICE, seed).split_whitespace() {
+            let n: i32 = word.parse().unwrap();
+            assert!((1..=6).contains(&n), "Got {} with seed {}", n, seed);
+        }
+    }
+}
+
+#[test]
+fn backtracking_does_not_repeat_numbers() {
+    // Each retry of length draws a new number, so ten draws aren't all the same
+    let text = seeded(DICE, 5);
+    let first = text.split_whitespace().next().unwrap();
+
+    assert!(text.split_whitespace().any(|word| word != first), "Got: {}", text);
+}
+
+#[test]
+fn seed_instruction_restarts_the_sequence() {
+    let source = "
+        int 9
+        seed
+        random
+        int 9
+        seed
+        random
+        unify";
+
+    output(source);
+}
+
+#[test]
+fn random_floats_are_in_the_unit_interval() {
+    // The top of the stack is the left operand of a comparison
+    let source = "
+        random
+        dup
+        float 0.0
+        lte
+        float 1.0
+        swap
+        lt";
+
+    for seed in 0..20 {
+        seeded(source, seed);
+    }
+}
+
+#[test]
+fn randint_fails_on_an_empty_range() {
+    failure("
+        int 1
+        int 6
+        randint");
+}
@@ -0,0 +1,47 @@
+use super::output;
+
+#[test]
+fn substitutes_nested_bindings() {
+    let source = "
+        term g(Z)
+        var Y
+        unify
+        int 3
+        var Z
+        unify
+        term f(Y, [Z, W])
+        resolve
+        print";
+
+    assert_eq!(output(source), "f(g(3), [3, W])");
+}
+
+#[test]
+fn follows_aliases() {
+    let source = "
+        var Y
+        var X
+        unify
+        atom done
+        var Y
+        unify
+        var X
+        resolve
+        isatom
+        str \"ok\"
+        print";
+
+    assert_eq!(output(source), "ok");
+}
+
+#[test]
+fn leaves_unbound_variables_alone() {
+    let source = "
+        var X
+        resolve
+        isvar
+        str \"ok\"
+        print";
+
+    assert_eq!(output(source), "ok");
+}
@@ -0,0 +1,58 @@
+use super::{expand, run_with};
+use crate::vm::{SearchOptions, SearchStrategy};
+
+// Runs the search example, which only finishes with a bounded search, after the given code
+fn run_search(before: &str, input: &str, strategy: SearchStrategy, max_depth: Option<usize>) -> String {
+    let source = format!("{}\n{}", before, expand(include_str!("../../examples/search.menvm")));
+
+    let (text, result) = run_with(&source, input, |vm| {
+        vm.set_seed(7);
+        vm.set_search(SearchOptions {
+            strategy,
+            max_depth,
+            ..SearchOptions::default()
+        });
+    });
+
+    match result {
+        Ok(()) => return text,
+        Err(err) => return format!("{}{}", text, err.msg_clone())
+    }
+}
+
+#[test]
+fn iterative_deepening_and_breadth_first_find_what_depth_first_misses() {
+    assert_eq!(run_search("", "", SearchStrategy::IterativeDeepening, None), "found X = s(s(z))\n");
+    assert_eq!(run_search("", "", SearchStrategy::BreadthFirst, None), "found X = s(s(z))\n");
+}
+
+#[test]
+fn iterative_deepening_stops_at_the_max_depth() {
+    let text = run_search("", "", SearchStrategy::IterativeDeepening, Some(1));
+    assert!(!text.starts_with("found"), "Got: {}", text);
+}
+
+// Prints, reads a line, and picks a random number before searching
+const SIDE_EFFECTS: &str = "
+    str \"start~n\"
+    format
+    readline
+    str \"read ~w~n\"
+    format
+    int 1000
+    int 1
+    randint
+    str \"random ~w~n\"
+    format
+";
+
+#[test]
+fn iterative_deepening_restarts_are_invisible() {
+    let deepening = run_search(SIDE_EFFECTS, "one\ntwo\n", SearchStrategy::IterativeDeepening, None);
+
+    // Depth first with a big enough bound goes straight to the answer, so it never restarts
+    let depth_first = run_search(SIDE_EFFECTS, "one\ntwo\n", SearchStrategy::DepthFirst, Some(5));
+
+    assert!(deepening.starts_with("start\nread one\nrandom "), "Got: {}", deepening);
+    assert_eq!(deepening, depth_first);
+}
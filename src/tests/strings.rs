@@ -0,0 +1,134 @@
+use super::{failure, output};
+
+#[test]
+fn concat_joins_two_strings() {
+    let source = "
+        var C
+        str \"def\"
+        str \"abc\"
+        concat
+        var C
+        print";
+
+    assert_eq!(output(source), "abcdef");
+}
+
+#[test]
+fn concat_splits_a_string_every_way_on_backtracking() {
+    let source = "
+        position done
+        gotochoice
+        str \"ab\"
+        var B
+        var A
+        concat
+        var B
+        var A
+        str \"[~w|~w]\"
+        format
+        fail
+        :done";
+
+    assert_eq!(output(source), "[|ab][a|b][ab|]");
+}
+
+#[test]
+fn strlen_counts_characters() {
+    assert_eq!(output("
+        str \"héllo\"
+        strlen
+        print"), "5");
+}
+
+#[test]
+fn substr_takes_characters_by_index() {
+    let source = "
+        int 3
+        int 1
+        str \"héllo\"
+        substr
+        print";
+
+    assert_eq!(output(source), "éll");
+}
+
+#[test]
+fn substr_fails_out_of_bounds() {
+    failure("
+        int 5
+        int 1
+        str \"hello\"
+        substr");
+
+    // The end would overflow a usize
+    failure("
+        int 18446744073709551615
+        int 1
+        str \"hello\"
+        substr");
+}
+
+#[test]
+fn strsplit_splits_on_a_separator() {
+    let source = "
+        str \",\"
+        str \"a,b,,c\"
+        strsplit
+        writeq";
+
+    assert_eq!(output(source), "[\"a\", \"b\", \"\", \"c\"]");
+}
+
+#[test]
+fn strindex_finds_each_occurrence_on_backtracking() {
+    let source = "
+        position done
+        gotochoice
+        str \"an\"
+        str \"banana\"
+        strindex
+        print
+        fail
+        :done";
+
+    assert_eq!(output(source), "13");
+}
+
+#[test]
+fn chars_converts_both_ways() {
+    assert_eq!(output("
+        var L
+        str \"hi\"
+        chars
+        var L
+        print"), "[104, 105]");
+
+    assert_eq!(output("
+        term [111, 107]
+        var S
+        chars
+        var S
+        print"), "ok");
+}
+
+#[test]
+fn tostr_and_parseint() {
+    assert_eq!(output("
+        term f(1, \"x\")
+        tostr
+        isstr
+        atom abc
+        tostr
+        writeq"), "\"abc\"");
+
+    assert_eq!(output("
+        str \" 42 \"
+        parseint
+        int 1
+        add
+        print"), "43");
+
+    failure("
+        str \"4x2\"
+        parseint");
+}
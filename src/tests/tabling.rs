@@ -0,0 +1,129 @@
+use super::{expand, output};
+
+// path(X, Y) :- path(X, Z), e(Z, Y).
+// path(X, Y) :- e(X, Y).
+// Without tabling, the left recursion would never terminate.
+const PATH: &str = "
+    position main
+    goto
+    :path
+    term path(X, Y)
+    unify
+    position path_base
+    gotochoice
+    term path(X, Z)
+    position path
+    tablecall
+    term e(Z, Y)
+    lookup
+    endgoal
+    :path_base
+    term e(X, Y)
+    lookup
+    endgoal
+    :main
+    term e(a, b)
+    assert
+    term e(b, c)
+    assert
+    term e(c, a)
+    assert
+";
+
+#[test]
+fn example_terminates_on_a_cyclic_graph() {
+    assert_eq!(output(&expand(include_str!("../../examples/tabling.menvm"))),
+               "Reachable pairs: [pair(a, a), pair(a, b), pair(a, c), pair(a, d), pair(b, a), pair(b, b), \
+                pair(b, c), pair(b, d), pair(c, a), pair(c, b), pair(c, c), pair(c, d)]\n");
+}
+
+#[test]
+fn left_recursion_finds_every_answer_once() {
+    let source = format!("{}
+        position done
+        gotochoice
+        term path(a, N)
+        position path
+        tablecall
+        var N
+        print
+        fail
+        :done", PATH);
+
+    assert_eq!(output(&source), "bca");
+}
+
+#[test]
+fn answers_are_filtered_by_later_unifications() {
+    let source = format!("{}
+        term path(b, N)
+        position path
+        tablecall
+        atom a
+        var N
+        unify
+        str \"yes\"
+        print", PATH);
+
+    assert_eq!(output(&source), "yes");
+}
+
+#[test]
+fn calls_without_answers_fail() {
+    let source = format!("{}
+        position done
+        gotochoice
+        term path(z, N)
+        position path
+        tablecall
+        str \"found\"
+        print
+        :done", PATH);
+
+    assert_eq!(output(&source), "");
+}
+
+#[test]
+fn goals_run_once_and_suspended_calls_get_later_answers() {
+    // Same as PATH, but printing each time the goal runs, on a longer chain
+    let source = "
+        position main
+        goto
+        :path
+        term path(X, Y)
+        unify
+        str \"run \"
+        print
+        position path_base
+        gotochoice
+        term path(X, Z)
+        position path
+        tablecall
+        term e(Z, Y)
+        lookup
+        endgoal
+        :path_base
+        term e(X, Y)
+        lookup
+        endgoal
+        :main
+        term e(a, b)
+        assert
+        term e(b, c)
+        assert
+        term e(c, d)
+        assert
+        term e(d, e)
+        assert
+        position done
+        gotochoice
+        term path(a, N)
+        position path
+        tablecall
+        var N
+        print
+        fail
+        :done";
+
+    assert_eq!(output(source), "run bcde");
+}
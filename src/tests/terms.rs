@@ -0,0 +1,48 @@
+use super::{failure, output};
+use crate::parse_instrs;
+
+#[test]
+fn literals_of_every_kind() {
+    assert_eq!(output("
+        term f(-1, 2.5, 1r2, \"a\\\"b\", 'X y', [g(Z), []], h)
+        writeq"), "f(-1, 2.5, 1r2, \"a\\\"b\", 'X y', [g(Z), []], h)");
+}
+
+#[test]
+fn named_variables_are_the_programs_variables() {
+    assert_eq!(output("
+        int 1
+        var X
+        unify
+        term f(X)
+        resolve
+        print"), "f(1)");
+}
+
+#[test]
+fn each_anonymous_variable_is_fresh() {
+    output("
+        term f(_, _)
+        term f(1, 2)
+        unify");
+
+    failure("
+        term f(X, X)
+        term f(1, 2)
+        unify");
+}
+
+#[test]
+fn quoted_functor_names() {
+    assert_eq!(output("
+        term 'hello world'(1)
+        arity
+        print"), "1");
+}
+
+#[test]
+fn malformed_terms_are_rejected_when_loading() {
+    for bad in &["term f(1, 2", "term [1, 2 | ]", "term f(1) junk", "list [1, 2"] {
+        assert!(parse_instrs(std::iter::once(bad.to_string())).is_none(), "Parsed: {}", bad);
+    }
+}
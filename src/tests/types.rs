@@ -0,0 +1,66 @@
+use super::run;
+
+// Whether the type test succeeds on a term given in term literal syntax
+fn is(test: &str, term: &str) -> bool {
+    let (_, result) = run(&format!("
+        term {}
+        {}", term, test), "");
+
+    return result.is_ok();
+}
+
+#[test]
+fn isvar_only_accepts_unbound_variables() {
+    assert!(is("isvar", "X"));
+    assert!(!is("isvar", "1"));
+    assert!(!is("isvar", "f(X)"));
+}
+
+#[test]
+fn isvar_follows_bindings() {
+    let (_, result) = run("
+        int 1
+        var X
+        unify
+        var X
+        isvar", "");
+
+    assert!(result.is_err());
+
+    let (_, result) = run("
+        var Y
+        var X
+        unify
+        var X
+        isvar", "");
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn value_tests_accept_only_their_kind() {
+    assert!(is("isint", "42"));
+    assert!(!is("isint", "4.2"));
+    assert!(is("isstr", "\"hi\""));
+    assert!(!is("isstr", "hi"));
+    assert!(is("isatom", "hi"));
+    assert!(!is("isatom", "\"hi\""));
+    assert!(is("isfunctor", "f(1)"));
+    assert!(is("isfunctor", "[1, 2]"));
+    assert!(!is("isfunctor", "X"));
+}
+
+#[test]
+fn isground_looks_inside_terms() {
+    assert!(is("isground", "f(a, [1, 2])"));
+    assert!(!is("isground", "f(a, [1, X])"));
+
+    let (_, result) = run("
+        int 2
+        var X
+        unify
+        term f(a, [1, X])
+        isground", "");
+
+    assert!(result.is_ok());
+}
@@ -1,6 +1,7 @@
 use std::collections::HashSet;
 
-use crate::stackitem::Value;
+use crate::fd::Domain;
+use crate::stackitem::{StackItem, Value};
 
 #[derive(Clone, Debug)]
 pub struct Unification {
@@ -9,7 +10,13 @@ pub struct Unification {
 
     // We can only be unified with at most one value, but we can be disunified with as many as we want
     pub value_unify: Option<Value>,
-    pub value_disunify: Vec<Value>
+    pub value_disunify: Vec<Value>,
+
+    // The finite domain of this variable, if it has been constrained (e.g., by `in`)
+    pub domain: Option<Domain>,
+
+    // An arbitrary term attached to this variable. Binding the variable wakes up the attribute hook.
+    pub attribute: Option<StackItem>
 }
 
 impl Unification {
@@ -18,7 +25,9 @@ impl Unification {
             var_unify: HashSet::new(),
             var_disunify: HashSet::new(),
             value_unify: None,
-            value_disunify: Vec::new()
+            value_disunify: Vec::new(),
+            domain: None,
+            attribute: None
         }
     }
 }
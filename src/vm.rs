@@ -0,0 +1,1465 @@
+#![allow(clippy::needless_return, clippy::single_match, clippy::redundant_field_names)]
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::atom::Atom;
+use crate::database::{Database, clause_key, number_vars};
+use crate::enkienv::{Environment, GcMark, Handler};
+use crate::err::Err;
+use crate::instr::Instr;
+use crate::parallel::{self, Outcome, Pool, Segment, SharedBuffer, Task, TaskResult};
+use crate::parser;
+use crate::random::Rng;
+use crate::stackitem::{StackItem, Value, empty_list, make_list};
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+pub const DEFAULT_GC_INTERVAL: usize = 10000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchStrategy {
+    DepthFirst,
+    IterativeDeepening, // Depth first with a bound, restarting with a larger bound if anything was cut off
+    BreadthFirst
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DepthMeasure {
+    Choicepoints, // Choices made on the way to the current state
+    Calls         // Jumps taken with goto on the way to the current state
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchOptions {
+    pub strategy: SearchStrategy,
+    pub measure: DepthMeasure,
+    pub max_depth: Option<usize>, // States deeper than this fail. For iterative deepening, the largest bound tried.
+    pub depth_step: usize         // How much iterative deepening increases the bound by each time
+}
+
+impl Default for SearchOptions {
+    fn default() -> SearchOptions {
+        SearchOptions {
+            strategy: SearchStrategy::DepthFirst,
+            measure: DepthMeasure::Choicepoints,
+            max_depth: None,
+            depth_step: 1
+        }
+    }
+}
+
+// Hitting any of these raises a resource error, which doesn't backtrack. A program can catch it with a
+// handler set by catch; otherwise it stops the program, and run returns it, so code embedding the VM can
+// tell it apart from failure with Err::is_resource. See catch_resource for how often it can be caught.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    pub max_steps: Option<usize>,
+    pub max_choicepoints: Option<usize>, // How many choicepoints can be waiting at once, in all workers together
+    pub max_stack: Option<usize>,
+    pub max_term_size: Option<usize>,    // Counted with variables replaced by their values, see check_term_size
+    pub timeout: Option<Duration>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FrameKind {
+    FindAll, // All solutions, possibly none
+    BagOf,   // All solutions, but fails if there are none. Free variables aren't grouped, see step.
+    SetOf,   // Like bagof, but sorted in the standard order without duplicates
+    Not,     // Succeeds, without binding anything, only if the goal has no solutions
+    Table    // Answers for a tabled call; run to exhaustion by evaluate_table rather than the main loop
+}
+
+#[derive(Clone)]
+struct Table {
+    answers: Vec<StackItem>,
+    seen: HashSet<String>, // The quoted form of each answer, to check for variants
+    complete: bool
+}
+
+impl Table {
+    // Returns whether the answer was new
+    fn add_answer(&mut self, answer: StackItem) -> bool {
+        if self.seen.insert(answer.quoted()) {
+            self.answers.push(answer);
+            return true;
+        } else {
+            return false;
+        }
+    }
+}
+
+// A goal running in isolation, started by findall and friends. The goal runs with no choicepoints, so once
+// it runs out of them we know every solution has been found, and we restore the saved environment.
+#[derive(Clone)]
+struct Frame {
+    kind: FrameKind,
+    saved_env: Environment,
+    template: StackItem,
+    result: StackItem,
+    results: Vec<StackItem>,
+    resume_idx: usize,
+    table: Option<String> // For tabled goals, the table their answers go to
+}
+
+// A call to a table that wasn't complete yet, suspended so it can be resumed with each answer found later.
+// The frames are the innermost tabled goal the call was made in and everything started inside it.
+#[derive(Clone)]
+struct Consumer {
+    table: String,
+    call: StackItem,
+    env: Environment,
+    frames: Vec<Frame>,
+    next_idx: usize,
+    consumed: usize // How many of the table's answers it has been given
+}
+
+// Everything a worker needs to continue a branch on its own, for or-parallel execution
+pub struct ForkedState {
+    env: Environment,
+    database: Database,
+    globals: HashMap<String, (u64, StackItem)>,
+    global_generation: u64,
+    tables: HashMap<String, Table>,
+    search: SearchOptions,
+    limits: Limits,
+    deadline: Option<Instant>,
+    rng: Rng,
+    gc_interval: usize
+}
+
+// Owns everything a running program needs that isn't part of the logical state in the Environment.
+// Reading input and writing output are side effects, so neither is undone when we backtrack.
+pub struct VM {
+    instrs: Arc<[Instr]>, // Shared with the tasks forked for or-parallel execution
+    pub env: Environment,
+    input: Box<dyn BufRead>,
+    input_log: Option<Vec<Option<String>>>, // Lines read so far while iterative deepening, to replay on each restart
+    input_pos: usize,                       // The next line of input_log to replay
+    output: Box<dyn Write>,
+    flush_output: bool,
+    database: Database,
+    globals: HashMap<String, (u64, StackItem)>, // Set by gset, and never restored by backtracking
+    global_generation: u64, // Incremented by each gset and bset, so gget can tell which one was last
+    frames: Vec<Frame>,
+    tables: HashMap<String, Table>, // Keyed by the quoted form of the call, with its variables numbered
+    active_tables: Vec<String>,     // Tables currently being evaluated, innermost last
+    consumers: Vec<Consumer>,       // Suspended calls to incomplete tables, in the order they were made
+    search: SearchOptions,
+    frontier: VecDeque<(usize, Environment)>, // States waiting to run, for breadth first search
+    pruned: bool, // Whether the depth bound cut off any states
+
+    // Or-parallel execution: with more than one job, top-level choicepoints are handed to other workers
+    jobs: usize,
+    deterministic: bool,
+    pool: Option<Arc<Pool>>,
+    path: Vec<u32>,
+    buffer: Option<Rc<RefCell<Vec<u8>>>>,
+    segments: Vec<Segment>,
+    reported_choicepoints: usize, // How many choicepoints the pool last heard this worker has waiting
+    steps: usize,
+    budget_renewed: bool, // Whether a handler already caught running out of steps or time
+    limits: Limits,
+    deadline: Option<Instant>,
+    rng: Rng, // Never restored by backtracking, so retrying doesn't give the same numbers again
+    gc_interval: usize // Steps between garbage collections of the unification table, or 0 to never collect
+}
+
+impl VM {
+    pub fn new(instrs: Vec<Instr>) -> VM {
+        return VM::with_io(instrs,
+                           Box::new(BufReader::new(std::io::stdin())),
+                           Box::new(BufWriter::new(std::io::stdout())));
+    }
+
+    pub fn with_io(instrs: Vec<Instr>, input: Box<dyn BufRead>, output: Box<dyn Write>) -> VM {
+        VM {
+            instrs: instrs.into(),
+            env: Environment::new(),
+            input,
+            input_log: None,
+            input_pos: 0,
+            output,
+            flush_output: false,
+            database: Database::new(),
+            globals: HashMap::new(),
+            global_generation: 0,
+            frames: Vec::new(),
+            tables: HashMap::new(),
+            active_tables: Vec::new(),
+            consumers: Vec::new(),
+            search: SearchOptions::default(),
+            frontier: VecDeque::new(),
+            pruned: false,
+            jobs: 1,
+            deterministic: false,
+            pool: None,
+            path: Vec::new(),
+            buffer: None,
+            segments: Vec::new(),
+            reported_choicepoints: 0,
+            steps: 0,
+            budget_renewed: false,
+            limits: Limits::default(),
+            deadline: None,
+            rng: Rng::from_time(),
+            gc_interval: DEFAULT_GC_INTERVAL
+        }
+    }
+
+    pub fn set_search(&mut self, search: SearchOptions) {
+        self.search = search;
+    }
+
+    // Only depth first search runs in parallel. With deterministic set, the output is the same as running
+    // sequentially; otherwise, output from each branch shows up in whatever order the branches finish.
+    pub fn set_jobs(&mut self, jobs: usize, deterministic: bool) {
+        self.jobs = jobs;
+        self.deterministic = deterministic;
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn set_gc_interval(&mut self, gc_interval: usize) {
+        self.gc_interval = gc_interval;
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = input;
+    }
+
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    // Flush after every instruction that writes output, e.g., so prompts show up before we read input
+    pub fn set_flush(&mut self, flush_output: bool) {
+        self.flush_output = flush_output;
+    }
+
+    pub fn flush(&mut self) -> Result<(), Err> {
+        return self.output.flush().map_err(|err| Err::new(format!("Could not flush output: {}", err)));
+    }
+
+    fn flush_if_needed(&mut self) -> Result<(), Err> {
+        if self.flush_output {
+            return self.flush();
+        } else {
+            return Ok(());
+        }
+    }
+
+    pub fn run(&mut self, debug: bool) -> Result<(), Err> {
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+
+        let result = if self.jobs > 1 && self.search.strategy == SearchStrategy::DepthFirst && !self.uses_shared_state() {
+            self.run_parallel()
+        } else {
+            self.run_instrs(debug)
+        };
+
+        // Whatever the program managed to print before failing should still show up
+        self.flush()?;
+
+        return result;
+    }
+
+    // Runs the instruction at idx, and returns the index of the instruction to run next
+    fn step(&mut self, idx: usize) -> Result<usize, Err> {
+        let instr = self.instrs[idx].clone();
+
+        let mut i = idx + 1;
+
+        let result = match instr {
+            Instr::Var(var_name) => self.env.push(StackItem::Variable(var_name)),
+            Instr::Fresh => {
+                let fresh_var = self.env.fresh_var();
+                self.env.push(fresh_var)
+            },
+            Instr::Fail => Err::err_res("fail".to_string()),
+            Instr::Print => self.env.print(&mut self.output).and_then(|_| self.flush_if_needed()),
+            Instr::Int(i) => self.env.push(StackItem::Value(Value::IntValue(i))),
+            Instr::Rational(r) => self.env.push(StackItem::Value(Value::RationalValue(r))),
+            Instr::Float(x) => self.env.push(StackItem::Value(Value::FloatValue(x))),
+            Instr::Str(s) => self.env.push(StackItem::Value(Value::StringValue(s))),
+            Instr::Atom(name) => self.env.push(StackItem::Value(Value::Atom(Atom::intern(&name)))),
+            Instr::List(item) => self.env.push_literal(&item),
+            Instr::Term(item) => self.env.push_literal(&item),
+            Instr::Unify   => self.env.unify(),
+            Instr::Disunify => self.env.disunify(),
+            Instr::Pop     => self.env.pop().map(|_x| ()), // Drop the returned item because we don't need it here
+            Instr::Dup     => self.env.dup(),
+            Instr::Project => self.env.project(),
+            Instr::NameOf  => self.env.nameof(),
+            Instr::Functor => self.env.functor(),
+            Instr::Swap    => self.env.swap(),
+            Instr::Destroy => self.env.destroy(),
+            Instr::Add  => self.env.add(),
+            Instr::Sub => self.env.sub(),
+            Instr::Mul => self.env.mul(),
+            Instr::Div => self.env.div(),
+            Instr::Pow => self.env.pow(),
+            Instr::Floor => self.env.floor(),
+            Instr::Ceiling => self.env.ceiling(),
+            Instr::Round => self.env.round(),
+            Instr::Truncate => self.env.truncate(),
+            Instr::ToFloat => self.env.tofloat(),
+            Instr::ToRational => self.env.torational(),
+            Instr::IsFloat => self.env.isfloat(),
+            Instr::IsRational => self.env.isrational(),
+            Instr::IsNumber => self.env.isnumber(),
+            Instr::Lt => self.env.lt(),
+            Instr::Gt => self.env.gt(),
+            Instr::Lte => self.env.lte(),
+            Instr::Gte => self.env.gte(),
+            Instr::Rot => self.env.rot(),
+            Instr::Over => self.env.over(),
+            Instr::WriteQ => self.env.writeq(&mut self.output).and_then(|_| self.flush_if_needed()),
+            Instr::Format => self.env.format(&mut self.output).and_then(|_| self.flush_if_needed()),
+            Instr::PrintStack => self.env.print_stack(&mut self.output).and_then(|_| self.flush_if_needed()),
+            Instr::PrintUnification => self.env.print_unification(&mut self.output).and_then(|_| self.flush_if_needed()),
+            Instr::FdIn => self.env.fd_in(),
+            Instr::FdEq => self.env.fd_eq(),
+            Instr::FdLt => self.env.fd_lt(),
+            Instr::AllDifferent => self.env.all_different(),
+            Instr::Label => self.env.label(i - 1),
+            Instr::AttrHook => self.env.attrhook(),
+            Instr::PutAttr => self.env.putattr(),
+            Instr::GetAttr => self.env.getattr(),
+            Instr::Compare => self.env.compare(),
+            Instr::TermLt => self.env.termlt(),
+            Instr::TermEq => self.env.termeq(),
+            Instr::IsVar => self.env.isvar(),
+            Instr::IsInt => self.env.isint(),
+            Instr::IsStr => self.env.isstr(),
+            Instr::IsAtom => self.env.isatom(),
+            Instr::IsFunctor => self.env.isfunctor(),
+            Instr::IsGround => self.env.isground(),
+            Instr::Arity => self.env.arity(),
+            Instr::Univ => self.env.univ(),
+            Instr::SetArg => self.env.setarg(),
+            Instr::CopyTerm => self.env.copyterm(),
+            Instr::Resolve => self.env.resolve(),
+            Instr::Concat => self.env.concat(i),
+            Instr::StrLen => self.env.strlen(),
+            Instr::SubStr => self.env.substr(),
+            Instr::StrSplit => self.env.strsplit(),
+            Instr::StrIndex => self.env.strindex(i),
+            Instr::Chars => self.env.chars(),
+            Instr::ToStr => self.env.tostr(),
+            Instr::ParseInt => self.env.parseint(),
+            Instr::Length => self.env.length(i - 1),
+            Instr::Append => self.env.append(i),
+            Instr::Nth => self.env.nth(i),
+            Instr::Reverse => self.env.reverse(),
+            Instr::Assert => self.assert(),
+            Instr::Retract => self.retract(),
+            Instr::Lookup => self.lookup(i),
+            Instr::GSet => self.gset(),
+            Instr::GGet => self.gget(),
+            Instr::BSet => self.bset(),
+            Instr::FindAll | Instr::BagOf | Instr::SetOf => {
+                let kind = match instr {
+                    Instr::BagOf => FrameKind::BagOf,
+                    Instr::SetOf => FrameKind::SetOf,
+                    _ => FrameKind::FindAll
+                };
+
+                // Pops the index of the goal, then the template, then the term to unify with the results.
+                // The goal is just an index into the code, not a term, so there's no way to tell which of
+                // its variables are free. bagof and setof therefore never group the solutions by the
+                // bindings of free variables the way Prolog's do: they're findall that fails when there
+                // are no solutions, and setof also sorts. Any variable not in the template is treated as
+                // if it were existentially quantified (^) in Prolog.
+                match (self.env.popidx(), self.env.pop(), self.env.pop()) {
+                    (Ok(goal_idx), Ok(template), Ok(result)) => {
+                        self.start_goal(kind, template, result, i, None);
+                        i = goal_idx;
+                        Ok(())
+                    }
+                    (Err(err), _, _) | (_, Err(err), _) | (_, _, Err(err)) => Err(err)
+                }
+            }
+            Instr::Not => {
+                match self.env.popidx() {
+                    Ok(goal_idx) => {
+                        // Nothing is collected, so the template and result are just placeholders
+                        self.start_goal(FrameKind::Not, empty_list(), empty_list(), i, None);
+                        i = goal_idx;
+                        Ok(())
+                    }
+                    Err(err) => Err(err)
+                }
+            }
+            Instr::EndGoal => self.endgoal(),
+            Instr::TableCall => self.tablecall(i),
+            Instr::Random => self.random(),
+            Instr::RandInt => self.randint(),
+            Instr::Seed => self.seed(),
+            Instr::Catch => self.catch(),
+            Instr::EndCatch => self.endcatch(),
+            Instr::ReadLine => self.readline(),
+            Instr::ReadInt => self.readint(),
+            Instr::ReadTerm => self.readterm(),
+            Instr::Goto => {
+                match self.env.popidx() {
+                    Ok(idx) => {
+                        self.env.calls += 1;
+                        i = idx;
+                        Ok(()) // TODO: Should it be an error if i >= self.instrs.len()?
+                    }
+                    Err(err) => Err(err)
+                }
+            },
+            Instr::GotoChoice => { // This adds a choicepoint. If we fail, we'll jump to the location indicated by idx at the top of the stack
+                match self.env.popidx() {
+                    Ok(idx) => {
+                        self.env.depth += 1;
+
+                        if self.can_fork() {
+                            self.fork(idx);
+                        } else {
+                            self.env.set_choicepoint(Some((idx, Box::new(self.env.clone()))));
+                        }
+
+                        Ok(())
+                    }
+                    Err(err) => Err(err)
+                }
+            }
+        }.and_then(|_| self.env.propagate()).and_then(|_| self.env.wake(i));
+
+        match result? {
+            Some(hook_idx) => return Ok(hook_idx),
+            None => return Ok(i)
+        }
+    }
+
+    fn run_instrs(&mut self, debug: bool) -> Result<(), Err> {
+        match self.search.strategy {
+            SearchStrategy::IterativeDeepening => self.run_iterative_deepening()?,
+            _ => self.run_search(0, self.search.max_depth)?
+        }
+
+        // Debug output goes to stderr so it never mixes with the program's own output
+        if debug {
+            eprintln!();
+            eprintln!("Stack at end of program:");
+            eprintln!("{:?}", self.env.resolved_data()?);
+            eprintln!();
+
+            eprintln!("Unification state at end of program:");
+            eprintln!("{:?}", self.env.unified);
+            eprintln!();
+        }
+
+        return Ok(());
+    }
+
+    fn run_search(&mut self, start_idx: usize, bound: Option<usize>) -> Result<(), Err> {
+        let mut i = start_idx;
+
+        loop {
+            if i >= self.instrs.len() {
+                return Ok(());
+            }
+
+            // Checking is relatively expensive, because it needs a lock
+            if self.steps.is_multiple_of(256) && self.cancelled() {
+                return Err::err_res("Cancelled".to_string());
+            }
+
+            match self.check_limits() {
+                Ok(()) => {}
+                Err(err) => {
+                    i = self.catch_resource(err)?;
+                    continue;
+                }
+            }
+
+            // Tabled goals run nested inside a step, holding terms we can't see, so we only collect out here
+            if self.gc_interval > 0 && self.steps.is_multiple_of(self.gc_interval) {
+                self.collect_garbage();
+            }
+
+            match self.step(i).and_then(|next_idx| self.check_depth(bound).map(|_| next_idx)) {
+                Ok(next_idx) => {
+                    i = next_idx;
+
+                    // Goals run by findall and friends need their own choicepoints to know when they're done,
+                    // so they're always searched depth first.
+                    if self.search.strategy == SearchStrategy::BreadthFirst && self.frames.is_empty() && self.env.choicepoint.is_some() {
+                        i = self.enqueue_choicepoints(i);
+                    }
+                }
+
+                Err(err) if err.is_resource() => i = self.catch_resource(err)?,
+                Err(err) => {
+                    match self.backtrack(0) {
+                        Some(idx) => i = idx,
+                        None => return Err(err)
+                    }
+                }
+            }
+        }
+    }
+
+    // Goes to the most recent handler, returning where it starts, or returns the error if there's no handler
+    // that can catch it. Only the top level catches: a handler set inside a tabled goal is gone along with
+    // the goal's environment by the time the error gets here. The step count and the time only go up, so
+    // running out of either can only be caught once, and the handler gets a new budget. That way, a program
+    // never runs for more than twice its limits.
+    fn catch_resource(&mut self, err: Err) -> Result<usize, Err> {
+        let out_of_budget = self.limits.max_steps.is_some_and(|max_steps| self.steps > max_steps) ||
+                            self.deadline.is_some_and(|deadline| Instant::now() > deadline);
+
+        if out_of_budget && self.budget_renewed {
+            return Err(err);
+        }
+
+        let handler = match self.env.handler.take() {
+            Some(handler) => handler,
+            None => return Err(err)
+        };
+
+        if out_of_budget {
+            self.budget_renewed = true;
+            self.steps = 0;
+            self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        }
+
+        self.frames.truncate(handler.frames);
+        self.restore_env(*handler.saved);
+        self.env.push(StackItem::Value(Value::StringValue(err.msg_clone())))?;
+
+        return Ok(handler.idx);
+    }
+
+    // Frames hold terms that aren't on any stack, so they keep their variables alive in every environment.
+    // Globals and table answers are stored with numbered variables, which are never collected.
+    fn collect_garbage(&mut self) {
+        let mut roots = Vec::new();
+
+        for frame in &self.frames {
+            frame.template.vars(&mut roots);
+            frame.result.vars(&mut roots);
+
+            for item in &frame.results {
+                item.vars(&mut roots);
+            }
+        }
+
+        self.env.collect_garbage(&roots);
+
+        for frame in &mut self.frames {
+            frame.saved_env.collect_garbage(&roots);
+        }
+
+        for (_, env) in &mut self.frontier {
+            env.collect_garbage(&roots);
+        }
+    }
+
+    // Runs the whole program with increasing depth bounds until it succeeds, or fails without the bound cutting
+    // anything off. Each restart begins from a clean slate, and the program behaves as if only the last
+    // iteration ran: output is buffered and only written for the last iteration, input read by an earlier
+    // iteration is read again from a log, and the random number generator starts from the same state each
+    // time. Because of the buffering, nothing is written until the search finishes, even with set_flush.
+    fn run_iterative_deepening(&mut self) -> Result<(), Err> {
+        let rng = self.rng.clone();
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let output = std::mem::replace(&mut self.output, Box::new(SharedBuffer(buffer.clone())));
+        self.input_log = Some(Vec::new());
+
+        let result = self.run_deepening_iterations(&rng, &buffer);
+
+        self.output = output;
+        self.input_log = None;
+
+        let bytes = std::mem::take(&mut *buffer.borrow_mut());
+        self.output.write_all(&bytes).map_err(|err| Err::new(format!("Could not write output: {}", err)))?;
+
+        return result;
+    }
+
+    fn run_deepening_iterations(&mut self, rng: &Rng, buffer: &Rc<RefCell<Vec<u8>>>) -> Result<(), Err> {
+        let step = self.search.depth_step.max(1);
+        let mut bound = step;
+
+        loop {
+            self.pruned = false;
+
+            match self.run_search(0, Some(bound)) {
+                Ok(()) => return Ok(()),
+                Err(err) if err.is_resource() => return Err(err),
+                Err(err) => {
+                    let at_max = self.search.max_depth.is_some_and(|max_depth| bound >= max_depth);
+
+                    if !self.pruned || at_max {
+                        return Err(err);
+                    }
+                }
+            }
+
+            buffer.borrow_mut().clear();
+            self.reset(rng);
+            bound += step;
+        }
+    }
+
+    fn run_parallel(&mut self) -> Result<(), Err> {
+        let root = Task {
+            path: Vec::new(),
+            start_idx: 0,
+            instrs: self.instrs.clone(),
+            state: self.fork_state()
+        };
+
+        return parallel::run_tasks(self.jobs, self.deterministic, root, &mut self.output);
+    }
+
+    // Runs a branch in a worker thread. Workers can't share stdin, so they have no input.
+    pub fn run_task(pool: Arc<Pool>, task: Task) -> TaskResult {
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+
+        let mut vm = VM::with_io(Vec::new(), Box::new(std::io::empty()), Box::new(SharedBuffer(buffer.clone())));
+        vm.instrs = task.instrs;
+        vm.restore_forked(task.state);
+        vm.pool = Some(pool.clone());
+        vm.path = task.path;
+        vm.buffer = Some(buffer);
+
+        let max_depth = vm.search.max_depth;
+
+        let outcome = match vm.run_search(task.start_idx, max_depth) {
+            Ok(()) => Outcome::Succeeded,
+            Err(err) if err.is_resource() => Outcome::Aborted(err),
+            Err(_) if vm.cancelled() => Outcome::Cancelled,
+            Err(err) => Outcome::Failed(err)
+        };
+
+        vm.end_segment();
+        pool.update_choicepoints(vm.reported_choicepoints, 0);
+
+        return TaskResult {
+            path: vm.path,
+            segments: vm.segments,
+            outcome: outcome
+        };
+    }
+
+    fn fork_state(&self) -> ForkedState {
+        ForkedState {
+            env: self.env.clone(),
+            database: self.database.clone(),
+            globals: self.globals.clone(),
+            global_generation: self.global_generation,
+            tables: self.tables.clone(),
+            search: self.search.clone(),
+            limits: self.limits.clone(),
+            deadline: self.deadline,
+            rng: self.rng.clone(),
+            gc_interval: self.gc_interval
+        }
+    }
+
+    fn restore_forked(&mut self, state: ForkedState) {
+        self.env = state.env;
+        self.database = state.database;
+        self.globals = state.globals;
+        self.global_generation = state.global_generation;
+        self.tables = state.tables;
+        self.search = state.search;
+        self.limits = state.limits;
+        self.deadline = state.deadline;
+        self.rng = state.rng;
+        self.gc_interval = state.gc_interval;
+    }
+
+    // Whether the program uses state that isn't part of the Environment and so isn't restored by backtracking:
+    // the database, gset globals, tables, the random number generator and input. A sequential run passes
+    // changes to it from one branch on to the next, but each worker would get its own copy, so programs that
+    // use any of it always run sequentially. So do programs that catch resource errors, since catching one
+    // can renew the step budget that all workers share.
+    fn uses_shared_state(&self) -> bool {
+        return self.instrs.iter().any(|instr| {
+            matches!(instr, Instr::Assert | Instr::Retract | Instr::GSet | Instr::TableCall | Instr::Random |
+                            Instr::RandInt | Instr::Seed | Instr::ReadLine | Instr::ReadInt | Instr::ReadTerm |
+                            Instr::Catch)
+        });
+    }
+
+    // Only top-level choicepoints are forked: goals run by findall and friends need their own choicepoints,
+    // and once this branch has a choicepoint, the alternatives should stay in order after it.
+    fn can_fork(&self) -> bool {
+        return self.pool.is_some() && self.frames.is_empty() && self.env.choicepoint.is_none();
+    }
+
+    // Hands the alternative at idx to another worker, and continues this branch as the first child
+    fn fork(&mut self, idx: usize) {
+        self.end_segment();
+
+        let mut alt_path = self.path.clone();
+        alt_path.push(1);
+        self.path.push(0);
+
+        let state = self.fork_state();
+
+        let task = Task {
+            path: alt_path,
+            start_idx: idx,
+            instrs: self.instrs.clone(),
+            state: state
+        };
+
+        match &self.pool {
+            Some(pool) => pool.submit(task),
+            None => {}
+        }
+    }
+
+    // Everything printed since the last fork belongs to the current path
+    fn end_segment(&mut self) {
+        match &self.buffer {
+            Some(buffer) => {
+                let bytes = std::mem::take(&mut *buffer.borrow_mut());
+
+                if !bytes.is_empty() {
+                    self.segments.push((self.path.clone(), bytes));
+                }
+            }
+
+            None => {}
+        }
+    }
+
+    fn cancelled(&self) -> bool {
+        match &self.pool {
+            Some(pool) => return pool.should_stop(&self.path),
+            None => return false
+        }
+    }
+
+    // Goes back to the state at the start of the run, with the random number generator in the given state
+    fn reset(&mut self, rng: &Rng) {
+        self.env = Environment::new();
+        self.database = Database::new();
+        self.globals.clear();
+        self.global_generation = 0;
+        self.frames.clear();
+        self.tables.clear();
+        self.active_tables.clear();
+        self.consumers.clear();
+        self.frontier.clear();
+        self.input_pos = 0;
+        self.steps = 0;
+        self.budget_renewed = false;
+        self.rng = rng.clone();
+    }
+
+    fn check_limits(&mut self) -> Result<(), Err> {
+        self.steps += 1;
+
+        // In parallel, the limits count steps and choicepoints in every worker
+        let (steps, choicepoints) = match &self.pool {
+            Some(pool) => {
+                let choicepoints = pool.update_choicepoints(self.reported_choicepoints, self.env.choicepoint_count);
+                self.reported_choicepoints = self.env.choicepoint_count;
+                (pool.count_step(), choicepoints)
+            }
+
+            None => (self.steps, self.env.choicepoint_count)
+        };
+
+        match self.limits.max_steps {
+            Some(max_steps) if steps > max_steps => {
+                return Err(Err::resource(format!("Exceeded the limit of {} steps", max_steps)));
+            }
+            _ => {}
+        }
+
+        match self.limits.max_stack {
+            Some(max_stack) if self.env.data.len() > max_stack => {
+                return Err(Err::resource(format!("Exceeded the stack limit of {} items", max_stack)));
+            }
+            _ => {}
+        }
+
+        match self.limits.max_choicepoints {
+            Some(max_choicepoints) if choicepoints > max_choicepoints => {
+                return Err(Err::resource(format!("Exceeded the limit of {} choicepoints", max_choicepoints)));
+            }
+            _ => {}
+        }
+
+        match self.limits.max_term_size {
+            Some(max_term_size) if self.check_term_size(max_term_size)? > max_term_size => {
+                return Err(Err::resource(format!("Exceeded the term size limit of {}", max_term_size)));
+            }
+            _ => {}
+        }
+
+        // Checking the time is relatively expensive, so don't do it every step
+        match self.deadline {
+            Some(deadline) if self.steps.is_multiple_of(256) && Instant::now() > deadline => {
+                return Err(Err::resource("Exceeded the time limit".to_string()));
+            }
+            _ => {}
+        }
+
+        return Ok(());
+    }
+
+    // Returns the size of the biggest term we looked at. Terms are mostly built on top of the stack, so the
+    // top item is checked every step. Binding a variable can make any term containing it bigger, wherever it
+    // is, so every 256 steps we also check everything on the stack and every value in the unification table.
+    fn check_term_size(&self, limit: usize) -> Result<usize, Err> {
+        let mut biggest = 0;
+
+        if self.steps.is_multiple_of(256) {
+            for item in &self.env.data {
+                biggest = biggest.max(self.env.term_size(item, limit)?);
+            }
+
+            biggest = biggest.max(self.env.max_bound_term_size(limit)?);
+        } else if let Some(item) = self.env.data.front() {
+            biggest = self.env.term_size(item, limit)?;
+        }
+
+        return Ok(biggest);
+    }
+
+    fn current_depth(&self) -> usize {
+        match self.search.measure {
+            DepthMeasure::Choicepoints => return self.env.depth,
+            DepthMeasure::Calls => return self.env.calls
+        }
+    }
+
+    fn check_depth(&mut self, bound: Option<usize>) -> Result<(), Err> {
+        match bound {
+            Some(max_depth) if self.current_depth() > max_depth => {
+                self.pruned = true;
+                return Err::err_res(format!("Exceeded the depth bound of {}", max_depth));
+            }
+
+            _ => return Ok(())
+        }
+    }
+
+    // Moves the current state and every alternative to the back of the frontier, then continues with the
+    // state at the front. Returns the index to continue at.
+    fn enqueue_choicepoints(&mut self, next_idx: usize) -> usize {
+        let mut chain = self.env.take_choicepoint();
+
+        self.frontier.push_back((next_idx, self.env.clone()));
+
+        while let Some((idx, mut alt)) = chain {
+            chain = alt.take_choicepoint();
+            self.frontier.push_back((idx, *alt));
+        }
+
+        match self.frontier.pop_front() {
+            Some((idx, env)) => {
+                self.restore_env(env);
+                return idx;
+            }
+
+            None => return next_idx // Can't happen: we just pushed the current state
+        }
+    }
+
+    // Like Environment::backtrack, but when a goal started by findall runs out of choicepoints, finishes it
+    // and continues after the findall instead. Frames below base_depth belong to someone else (e.g., a tabled
+    // goal that's running in its own loop), so they're left alone. At the top level, also tries the
+    // states waiting for breadth first search.
+    fn backtrack(&mut self, base_depth: usize) -> Option<usize> {
+        loop {
+            match self.env.backtrack() {
+                Some(idx) => return Some(idx),
+                None => {}
+            }
+
+            if self.frames.len() <= base_depth {
+                if base_depth > 0 {
+                    return None;
+                }
+
+                let (idx, env) = self.frontier.pop_front()?;
+                self.restore_env(env);
+                return Some(idx);
+            }
+
+            let frame = self.frames.pop()?;
+
+            match self.finish_goal(frame) {
+                Ok(idx) => return Some(idx),
+                Err(_) => {} // Finishing failed, so keep backtracking in the restored environment
+            }
+        }
+    }
+
+    // The caller jumps to the goal afterwards, which should end with endgoal
+    fn start_goal(&mut self, kind: FrameKind, template: StackItem, result: StackItem, resume_idx: usize, table: Option<String>) {
+        // The goal gets a copy of the environment with no choicepoints, and the real chain is kept in the frame
+        let chain = self.env.take_choicepoint();
+        let mut saved_env = self.env.clone();
+        saved_env.set_choicepoint(chain);
+
+        self.frames.push(Frame {
+            kind: kind,
+            saved_env: saved_env,
+            template: template,
+            result: result,
+            results: Vec::new(),
+            resume_idx: resume_idx,
+            table: table
+        });
+    }
+
+    // Records a solution of the current goal, then fails to look for the next one
+    pub fn endgoal(&mut self) -> Result<(), Err> {
+        let frame = match self.frames.last() {
+            Some(frame) => frame,
+            None => return Err::err_res("endgoal outside of a goal".to_string())
+        };
+
+        // One solution is enough to make not fail, so we throw away the goal's remaining choicepoints
+        if frame.kind == FrameKind::Not {
+            match self.frames.pop() {
+                Some(frame) => self.restore_env(frame.saved_env),
+                None => {}
+            }
+
+            return Err::err_res("Negated goal succeeded".to_string());
+        }
+
+        let solution = number_vars(&self.env.resolve_item(&frame.template)?, &mut HashMap::new());
+
+        // Answers to tabled goals go straight into their table, so consumers resumed later can see them
+        match &frame.table {
+            Some(key) => {
+                match self.tables.get_mut(key) {
+                    Some(table) => {
+                        table.add_answer(solution);
+                    }
+                    None => return Err::err_res(format!("Lost the table for: {}", key))
+                }
+            }
+
+            None => {
+                match self.frames.last_mut() {
+                    Some(frame) => frame.results.push(solution),
+                    None => {}
+                }
+            }
+        }
+
+        return Err::err_res("endgoal".to_string());
+    }
+
+    // Keeps the fresh counter, so we never reuse the names of variables made while running the goal
+    fn restore_env(&mut self, saved_env: Environment) {
+        let fresh_counter = self.env.fresh_counter.max(saved_env.fresh_counter);
+        self.env = saved_env;
+        self.env.fresh_counter = fresh_counter;
+        self.env.collected = GcMark::default();
+    }
+
+    // Restores the environment from before the goal started, and unifies the collected results
+    fn finish_goal(&mut self, frame: Frame) -> Result<usize, Err> {
+        self.restore_env(frame.saved_env);
+
+        if frame.kind == FrameKind::Not {
+            return Ok(frame.resume_idx);
+        }
+
+        if frame.kind == FrameKind::Table {
+            return Err::err_res("Tabled goals are finished by evaluate_table".to_string());
+        }
+
+        if frame.results.is_empty() && frame.kind != FrameKind::FindAll {
+            return Err::err_res("Goal has no solutions".to_string());
+        }
+
+        let mut items = Vec::new();
+
+        for solution in &frame.results {
+            items.push(self.env.copy_term(solution)?);
+        }
+
+        if frame.kind == FrameKind::SetOf {
+            let env = &self.env;
+            items.sort_by(|a, b| env.compare_items(a, b).unwrap_or(Ordering::Equal));
+            items.dedup_by(|a, b| env.compare_items(a, b).is_ok_and(|ord| ord == Ordering::Equal));
+        }
+
+        self.env.push(frame.result)?;
+        self.env.push(make_list(items, empty_list()))?;
+        self.env.unify()?;
+
+        return Ok(frame.resume_idx);
+    }
+
+    // Pops the index of the goal, then the call. The goal starts with a copy of the call on top of the stack
+    // and should unify it with the answer, ending with endgoal like the goals of findall. Each variant of a
+    // call is only evaluated once, so left recursive goals terminate as long as they have finitely many answers.
+    pub fn tablecall(&mut self, next_idx: usize) -> Result<(), Err> {
+        let goal_idx = self.env.popidx()?;
+        let call = self.env.pop()?;
+
+        let variant = number_vars(&self.env.resolve_item(&call)?, &mut HashMap::new());
+        let key = variant.quoted();
+
+        if !self.tables.contains_key(&key) {
+            self.evaluate_table(&key, &variant, goal_idx)?;
+        }
+
+        let (answers, complete) = match self.tables.get(&key) {
+            Some(table) => (table.answers.clone(), table.complete),
+            None => (Vec::new(), true)
+        };
+
+        // The table may get more answers before it's complete, so we suspend a copy of this call to be
+        // resumed with each of them. For now, we carry on with the answers found so far.
+        if !complete {
+            self.suspend_consumer(&key, &call, next_idx, answers.len())?;
+        }
+
+        if answers.is_empty() {
+            return Err::err_res(format!("No answers for tabled call: {}", key));
+        }
+
+        return self.env.choose(next_idx, answers, &|env, answer| unify_clause(env, &call, &answer));
+    }
+
+    fn suspend_consumer(&mut self, key: &str, call: &StackItem, next_idx: usize, consumed: usize) -> Result<(), Err> {
+        let first_frame = match self.frames.iter().rposition(|frame| frame.kind == FrameKind::Table) {
+            Some(idx) => idx,
+            None => return Err::err_res(format!("Incomplete table outside of a tabled goal: {}", key))
+        };
+
+        // The choicepoints belong to the goal that's running now, which will try them itself
+        let chain = self.env.take_choicepoint();
+        let env = self.env.clone();
+        self.env.set_choicepoint(chain);
+
+        self.consumers.push(Consumer {
+            table: key.to_string(),
+            call: call.clone(),
+            env: env,
+            frames: self.frames[first_frame..].to_vec(),
+            next_idx: next_idx,
+            consumed: consumed
+        });
+
+        return Ok(());
+    }
+
+    // Runs the goal once, then, if this is the outermost table being evaluated, resumes suspended calls with
+    // new answers until there are none. Tables only become complete once the outermost evaluation finishes,
+    // because until then they may depend on answers that haven't been found yet.
+    fn evaluate_table(&mut self, key: &str, variant: &StackItem, goal_idx: usize) -> Result<(), Err> {
+        self.tables.insert(key.to_string(), Table {
+            answers: Vec::new(),
+            seen: HashSet::new(),
+            complete: false
+        });
+
+        self.active_tables.push(key.to_string());
+
+        let mut result = self.run_tabled_goal(key, variant, goal_idx);
+
+        if result.is_ok() && self.active_tables.len() == 1 {
+            result = self.resume_consumers();
+        }
+
+        self.active_tables.pop();
+
+        if self.active_tables.is_empty() {
+            self.consumers.clear();
+
+            if result.is_ok() {
+                for table in self.tables.values_mut() {
+                    table.complete = true;
+                }
+            } else {
+                self.tables.retain(|_, table| table.complete);
+            }
+        }
+
+        return result;
+    }
+
+    // Gives each consumer the answers it hasn't seen yet, in the order the consumers were suspended. Resuming
+    // one can add answers and suspend more consumers, so we keep going until every consumer has every answer.
+    fn resume_consumers(&mut self) -> Result<(), Err> {
+        loop {
+            let mut resumed = false;
+            let mut idx = 0;
+
+            while idx < self.consumers.len() {
+                let consumer = &self.consumers[idx];
+
+                let answer = match self.tables.get(&consumer.table) {
+                    Some(table) => table.answers.get(consumer.consumed).cloned(),
+                    None => None
+                };
+
+                match answer {
+                    Some(answer) => {
+                        self.consumers[idx].consumed += 1;
+                        let consumer = self.consumers[idx].clone();
+                        self.resume_consumer(consumer, &answer)?;
+                        resumed = true;
+                    }
+
+                    None => idx += 1
+                }
+            }
+
+            if !resumed {
+                return Ok(());
+            }
+        }
+    }
+
+    // Continues a suspended call with one answer, until the tabled goal it was made in runs out of choicepoints
+    fn resume_consumer(&mut self, consumer: Consumer, answer: &StackItem) -> Result<(), Err> {
+        let base = self.frames.len();
+        let mut saved_env = consumer.env;
+
+        std::mem::swap(&mut self.env, &mut saved_env);
+        self.env.fresh_counter = self.env.fresh_counter.max(saved_env.fresh_counter);
+        self.frames.extend(consumer.frames);
+
+        let result = match unify_clause(&mut self.env, &consumer.call, answer) {
+            Ok(()) => self.run_nested(consumer.next_idx, base + 1),
+            Err(_) => Ok(())
+        };
+
+        self.frames.truncate(base);
+        self.restore_env(saved_env);
+
+        return result;
+    }
+
+    // Runs the goal in isolation until it runs out of choicepoints. Its answers are added to the table by endgoal.
+    fn run_tabled_goal(&mut self, key: &str, variant: &StackItem, goal_idx: usize) -> Result<(), Err> {
+        let call = self.env.copy_term(variant)?;
+
+        self.start_goal(FrameKind::Table, call.clone(), empty_list(), goal_idx, Some(key.to_string()));
+        let depth = self.frames.len();
+
+        let result = self.env.push(call).and_then(|_| self.run_nested(goal_idx, depth));
+
+        match self.frames.pop() {
+            Some(frame) => {
+                self.restore_env(frame.saved_env);
+                return result;
+            }
+
+            None => return Err::err_res("Lost the frame of a tabled goal".to_string())
+        }
+    }
+
+    // Like run_instrs, but stops when the frame at depth runs out of choicepoints
+    fn run_nested(&mut self, start_idx: usize, depth: usize) -> Result<(), Err> {
+        let mut i = start_idx;
+
+        loop {
+            if i >= self.instrs.len() {
+                return Err::err_res("Tabled goal ran past the end of the program without reaching endgoal".to_string());
+            }
+
+            self.check_limits()?;
+
+            match self.step(i) {
+                Ok(next_idx) => i = next_idx,
+                Err(err) if err.is_resource() => return Err(err),
+                Err(_) => {
+                    match self.backtrack(depth) {
+                        Some(idx) => i = idx,
+                        None => return Ok(())
+                    }
+                }
+            }
+        }
+    }
+
+    // Reads the next line without its line ending, or None at the end of the input
+    fn read_input_line(&mut self) -> Result<Option<String>, Err> {
+        if let Some(log) = &self.input_log {
+            if let Some(line) = log.get(self.input_pos) {
+                self.input_pos += 1;
+                return Ok(line.clone());
+            }
+        }
+
+        let line = self.read_new_input_line()?;
+
+        if let Some(log) = &mut self.input_log {
+            log.push(line.clone());
+            self.input_pos += 1;
+        }
+
+        return Ok(line);
+    }
+
+    fn read_new_input_line(&mut self) -> Result<Option<String>, Err> {
+        // Make sure any prompt we printed is visible before we wait for input
+        self.flush()?;
+
+        let mut line = String::new();
+
+        match self.input.read_line(&mut line) {
+            Ok(0) => return Ok(None),
+            Ok(_) => {
+                if line.ends_with('\n') {
+                    line.pop();
+
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+
+                return Ok(Some(line));
+            }
+            Err(err) => return Err::err_res(format!("Could not read input: {}", err))
+        }
+    }
+
+    fn push_end_of_file(&mut self) -> Result<(), Err> {
+        return self.env.push(StackItem::Value(Value::Atom(Atom::intern("end_of_file"))));
+    }
+
+    pub fn readline(&mut self) -> Result<(), Err> {
+        match self.read_input_line()? {
+            Some(line) => return self.env.push(StackItem::Value(Value::StringValue(line))),
+            None => return self.push_end_of_file()
+        }
+    }
+
+    pub fn readint(&mut self) -> Result<(), Err> {
+        match self.read_input_line()? {
+            Some(line) => {
+                match line.trim().parse() {
+                    Ok(i) => return self.env.push(StackItem::Value(Value::IntValue(i))),
+                    Err(_) => return Err::err_res(format!("Could not parse integer from input: {}", line))
+                }
+            }
+
+            None => return self.push_end_of_file()
+        }
+    }
+
+    // Reads one term per line, in the same syntax as the term instruction. The input's variables are
+    // given fresh names, so an X in the input is never the program's X.
+    pub fn readterm(&mut self) -> Result<(), Err> {
+        match self.read_input_line()? {
+            Some(line) => {
+                let mut item = parser::parse_term(&line)?;
+
+                let mut names = Vec::new();
+                item.vars(&mut names);
+
+                let mut subs_map = HashMap::new();
+                for name in names {
+                    if name != "_" && !subs_map.contains_key(&name) {
+                        if let StackItem::Variable(fresh) = self.env.fresh_var() {
+                            subs_map.insert(name, fresh);
+                        }
+                    }
+                }
+
+                item.substitute(&subs_map);
+                return self.env.push_literal(&item);
+            }
+
+            None => return self.push_end_of_file()
+        }
+    }
+
+    pub fn assert(&mut self) -> Result<(), Err> {
+        let item = self.env.pop()?;
+        let term = self.env.resolve_item(&item)?;
+
+        return self.database.assert(&term);
+    }
+
+    // Removes the first clause that unifies with the top item, and unifies them. Unlike lookup, this
+    // doesn't leave choicepoints for the other matching clauses.
+    pub fn retract(&mut self) -> Result<(), Err> {
+        let pattern = self.env.pop()?;
+        let key = clause_key(&self.env.resolve_item(&pattern)?)?;
+
+        // Don't copy the whole choicepoint chain into every attempt; we put it back afterwards
+        let chain = self.env.take_choicepoint();
+
+        for (idx, clause) in self.database.clauses(&key).into_iter().enumerate() {
+            let mut attempt = self.env.clone();
+
+            if unify_clause(&mut attempt, &pattern, &clause).is_ok() {
+                attempt.set_choicepoint(chain);
+                self.env = attempt;
+                self.database.remove(&key, idx);
+                return Ok(());
+            }
+        }
+
+        self.env.set_choicepoint(chain);
+
+        return Err::err_res(format!("No clause to retract matches: {}", self.env.resolve_item(&pattern)?));
+    }
+
+    // Unifies the top item with each matching clause in turn, in the order they were asserted
+    pub fn lookup(&mut self, next_idx: usize) -> Result<(), Err> {
+        let pattern = self.env.pop()?;
+        let key = clause_key(&self.env.resolve_item(&pattern)?)?;
+
+        let clauses = self.database.clauses(&key);
+
+        if clauses.is_empty() {
+            return Err::err_res(format!("No clauses for {}/{}", key.0, key.1));
+        }
+
+        return self.env.choose(next_idx, clauses, &|env, clause| unify_clause(env, &pattern, &clause));
+    }
+
+    // Pushes a float that's at least 0 and less than 1
+    pub fn random(&mut self) -> Result<(), Err> {
+        let x = self.rng.next_float();
+        return self.env.push(StackItem::Value(Value::FloatValue(x)));
+    }
+
+    // Pops Lo, then Hi, and pushes an integer between them, inclusive
+    pub fn randint(&mut self) -> Result<(), Err> {
+        let lo = self.env.popint()?;
+        let hi = self.env.popint()?;
+
+        if hi < lo {
+            return Err::err_res(format!("Cannot pick a random integer between {} and {}", lo, hi));
+        }
+
+        let size = (&hi - &lo + BigInt::from(1)).to_u64().ok_or(Err::new(format!("Range {} to {} is too large for randint", lo, hi)))?;
+        let offset = self.rng.below(size);
+
+        return self.env.push(StackItem::Value(Value::IntValue(lo + offset)));
+    }
+
+    // Restarts the random numbers from the popped seed. Negative seeds are fine: only the low 64 bits matter.
+    pub fn seed(&mut self) -> Result<(), Err> {
+        let seed = self.env.popint()?;
+        let bits = seed.to_u64().or_else(|| seed.to_i64().map(|x| x as u64))
+            .ok_or(Err::new(format!("Seed {} doesn't fit in 64 bits", seed)))?;
+
+        self.set_seed(bits);
+
+        return Ok(());
+    }
+
+    // Pops the position of a handler. Until endcatch, or until we backtrack to before this, a resource error
+    // runs the handler instead of stopping the program. The handler starts in the state catch saw, with the
+    // error message pushed as a string.
+    pub fn catch(&mut self) -> Result<(), Err> {
+        let idx = self.env.popidx()?;
+        let saved = Box::new(self.env.clone());
+
+        self.env.handler = Some(Handler {
+            idx: idx,
+            frames: self.frames.len(),
+            saved: saved
+        });
+
+        return Ok(());
+    }
+
+    // Puts back the handler that was there before the most recent catch
+    pub fn endcatch(&mut self) -> Result<(), Err> {
+        match self.env.handler.take() {
+            Some(mut handler) => {
+                self.env.handler = handler.saved.handler.take();
+                return Ok(());
+            }
+
+            None => return Err::err_res("endcatch without a matching catch".to_string())
+        }
+    }
+
+    // Global names can be written as atoms or strings
+    fn pop_global_name(&mut self) -> Result<String, Err> {
+        let item = self.env.pop()?;
+
+        match self.env.resolve_item(&item)? {
+            StackItem::Value(Value::Atom(atom)) => return Ok(atom.name()),
+            StackItem::Value(Value::StringValue(s)) => return Ok(s),
+            other => return Err::err_res(format!("Expected the name of a global, but got: {}", other))
+        }
+    }
+
+    // Pops a name, then a value. The value is copied, so later bindings of its variables don't affect it.
+    pub fn gset(&mut self) -> Result<(), Err> {
+        let name = self.pop_global_name()?;
+        let item = self.env.pop()?;
+        let value = number_vars(&self.env.resolve_item(&item)?, &mut HashMap::new());
+
+        self.global_generation += 1;
+        self.globals.insert(name, (self.global_generation, value));
+
+        return Ok(());
+    }
+
+    // Like gset, but the value is undone on backtracking, and it isn't copied
+    pub fn bset(&mut self) -> Result<(), Err> {
+        let name = self.pop_global_name()?;
+        let item = self.env.pop()?;
+
+        self.global_generation += 1;
+        self.env.globals.insert(name, (self.global_generation, item));
+
+        return Ok(());
+    }
+
+    // Gets whichever of the gset and bset values was set last. Backtracking can bring back a bset value
+    // that's older than a gset made since, so we compare generations rather than letting either one win.
+    pub fn gget(&mut self) -> Result<(), Err> {
+        let name = self.pop_global_name()?;
+
+        match (self.env.globals.get(&name), self.globals.get(&name)) {
+            (Some((bset_gen, item)), Some((gset_gen, _))) if bset_gen > gset_gen => {
+                return self.env.push(item.clone());
+            }
+
+            (Some((_, item)), None) => return self.env.push(item.clone()),
+
+            (_, Some((_, value))) => {
+                let copy = self.env.copy_term(value)?;
+                return self.env.push(copy);
+            }
+
+            (None, None) => return Err::err_res(format!("Global {} has not been set", name))
+        }
+    }
+}
+
+// Unifies the pattern with a copy of the clause that has fresh variables
+fn unify_clause(env: &mut Environment, pattern: &StackItem, clause: &StackItem) -> Result<(), Err> {
+    let copy = env.copy_term(clause)?;
+
+    env.push(pattern.clone())?;
+    env.push(copy)?;
+
+    return env.unify();
+}